@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use peernode::consumer;
 use peernode::consumer::encode;
@@ -12,6 +13,7 @@ use orcanet_market::BootNodes;
 use orcanet_market::Multiaddr;
 use proto::market::FileInfoHash;
 use store::Configurations;
+use tokio::sync::broadcast::error::RecvError;
 
 #[cfg(test)]
 mod tests;
@@ -106,6 +108,16 @@ pub fn cli() -> Command {
                         .arg(arg!(<FILE_HASH> "The hash of the file to list").required(true))
                         .arg_required_else_help(true),
                 )
+                .subcommand(
+                    Command::new("search")
+                        .about("Finds files by the words in their names")
+                        .arg(
+                            arg!(<QUERY> "The words to search for")
+                                .required(true)
+                                .num_args(1..),
+                        )
+                        .arg_required_else_help(true),
+                )
                 .subcommand(
                     Command::new("get")
                         .about("Downloads a file from a producer")
@@ -158,6 +170,15 @@ Otherwise, it will run in client mode and only retrieve data from the network.
 The address must be provided as a Multiaddr,
 e.g. /ip4/0.0.0.0/tcp/6881")
                     )
+                )
+                .subcommand(
+                    Command::new("events")
+                        .about("Shows what happens on the market network")
+                        .arg(
+                            arg!(<SECONDS> "How long to show the events for (default 10)")
+                                .required(false)
+                                .short('t'),
+                        ),
                 ),
         )
         .subcommand(Command::new("exit").about("Exits the CLI"))
@@ -291,6 +312,15 @@ pub async fn handle_arg_matches(
                     consumer::list_producers(file_info_hash, market_client).await?;
                     Ok(())
                 }
+                Some(("search", search_matches)) => {
+                    let query = match search_matches.get_many::<String>("QUERY") {
+                        Some(words) => words.cloned().collect::<Vec<_>>().join(" "),
+                        None => Err(anyhow!("No query provided"))?,
+                    };
+                    let market_client = config.get_market_client().await?;
+                    consumer::search_files(&query, market_client).await?;
+                    Ok(())
+                }
                 // get file from producer
                 Some(("get", get_matches)) => {
                     let file_hash = match get_matches.get_one::<String>("FILE_HASH") {
@@ -402,6 +432,24 @@ pub async fn handle_arg_matches(
                 config.get_market_client().await?;
                 Ok(())
             }
+            Some(("events", events_matches)) => {
+                let seconds = match events_matches.get_one::<String>("SECONDS") {
+                    Some(seconds) => seconds.parse::<u64>()?,
+                    None => 10,
+                };
+                let mut events = config.get_market_client().await?.subscribe();
+                let _ = tokio::time::timeout(Duration::from_secs(seconds), async {
+                    loop {
+                        match events.recv().await {
+                            Ok(event) => println!("{event:?}"),
+                            Err(RecvError::Lagged(missed)) => println!("Missed {missed} events"),
+                            Err(RecvError::Closed) => break,
+                        }
+                    }
+                })
+                .await;
+                Ok(())
+            }
             _ => Err(anyhow!("Invalid subcommand")),
         },
         Some(("exit", _)) => Ok(()),
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use clap::Parser;
 use orcanet_market::Multiaddr;
 
@@ -10,8 +12,17 @@ pub struct Cli {
     pub market_port: Port,
     #[arg(short, long, default_value = "16899")]
     pub peer_port: Port,
+    #[arg(short, long)]
+    pub quic_port: Option<Port>,
     #[arg(short, long, value_parser, num_args = 0.., value_delimiter = ',')]
     pub boot_nodes: Option<Vec<Multiaddr>>,
     #[arg(long)]
     pub public_address: Option<Multiaddr>,
+    #[arg(short, long)]
+    pub keypair_path: Option<PathBuf>,
+    #[arg(short, long)]
+    pub record_store_path: Option<PathBuf>,
+    /// Serves the Prometheus metrics of the peer on this port at `/metrics`
+    #[arg(long)]
+    pub metrics_port: Option<Port>,
 }
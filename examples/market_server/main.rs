@@ -1,4 +1,7 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
 
 pub type Port = u16;
 
@@ -6,7 +9,7 @@ use crate::{cli::Cli, market_service::MarketService};
 use anyhow::Result;
 use clap::Parser;
 use market_proto::market_proto_rpc::market_server::MarketServer;
-use orcanet_market::{bridge::spawn, config::BootNodes, config::Config};
+use orcanet_market::{bridge::spawn_in_current_runtime, config::BootNodes, config::Config};
 use tokio::runtime::Runtime;
 use tonic::transport::Server;
 use tracing::info;
@@ -23,8 +26,12 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
     let market_port = cli.market_port;
     let peer_port = cli.peer_port;
+    let quic_port = cli.quic_port;
     let boot_nodes = cli.boot_nodes;
     let public_address = cli.public_address;
+    let keypair_path = cli.keypair_path;
+    let record_store_path = cli.record_store_path;
+    let metrics_port = cli.metrics_port;
 
     let mut config = Config::builder();
     if let Some(boot_nodes) = boot_nodes {
@@ -33,25 +40,42 @@ fn main() -> Result<()> {
     if let Some(public_address) = public_address {
         config = config.set_public_address(public_address);
     }
+    if let Some(keypair_path) = keypair_path {
+        config = config.set_keypair_path(keypair_path);
+    }
+    if let Some(record_store_path) = record_store_path {
+        config = config.set_record_store_path(record_store_path);
+    }
+    if let Some(quic_port) = quic_port {
+        config = config.set_peer_quic_port(quic_port);
+    }
     config = config.set_peer_tcp_port(peer_port);
     let config = config.build();
 
-    let peer = spawn(config)?;
     let market_listen_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), market_port);
-    let market_service = MarketService::new(peer);
-
-    info!("Market is listening on {}", market_listen_addr);
-    Runtime::new()
-        .unwrap()
-        .block_on(
-            Server::builder()
-                .add_service(MarketServer::new(market_service))
-                .serve(market_listen_addr),
-        )
-        .unwrap();
-
-    Ok(())
+    Runtime::new().unwrap().block_on(async move {
+        let peer = Arc::new(spawn_in_current_runtime(config).await?);
+        let market_service = MarketService::new(peer.clone());
+        if let Some(metrics_port) = metrics_port {
+            let metrics_listen_addr =
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), metrics_port);
+            info!("Metrics are served on {}", metrics_listen_addr);
+            tokio::spawn(metrics::serve(metrics_listen_addr, peer.clone()));
+        }
+
+        info!("Market is listening on {}", market_listen_addr);
+        Server::builder()
+            .add_service(MarketServer::new(market_service))
+            .serve_with_shutdown(market_listen_addr, async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await?;
+        info!("Shutting down the market");
+        peer.shutdown().await?;
+        Ok(())
+    })
 }
 
 mod cli;
 mod market_service;
+mod metrics;
@@ -1,16 +1,18 @@
 use market_proto::market_proto_rpc::{
     market_server::Market, CheckHoldersRequest, HoldersResponse, RegisterFileRequest,
 };
+use std::sync::Arc;
+
 use orcanet_market::Peer;
 use tonic::{Request, Response, Status};
 
 #[derive(Debug)]
 pub struct MarketService {
-    peer: Peer,
+    peer: Arc<Peer>,
 }
 
 impl MarketService {
-    pub fn new(peer: Peer) -> Self {
+    pub fn new(peer: Arc<Peer>) -> Self {
         MarketService { peer }
     }
 }
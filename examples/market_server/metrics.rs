@@ -0,0 +1,35 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use orcanet_market::{Peer, SuccessfulResponse};
+use tokio::net::TcpListener;
+use tracing::error;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+pub async fn serve(addr: SocketAddr, peer: Arc<Peer>) {
+    let app = Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(peer);
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed to listen on {} for metrics: {}", addr, err);
+            return;
+        }
+    };
+    if let Err(err) = axum::serve(listener, app).await {
+        error!("Metrics server stopped: {}", err);
+    }
+}
+
+async fn metrics(
+    State(peer): State<Arc<Peer>>,
+) -> Result<([(&'static str, &'static str); 1], String), StatusCode> {
+    match peer.prometheus_metrics().await {
+        Ok(SuccessfulResponse::PrometheusMetrics { text }) => {
+            Ok(([("content-type", CONTENT_TYPE)], text))
+        }
+        _ => Err(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
@@ -0,0 +1,123 @@
+use libp2p::{gossipsub::IdentTopic, PeerId};
+use proto::market::{FileInfo, FileInfoHash};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::lmm::SupplierInfo;
+
+const ANNOUNCEMENT_TOPIC: &str = "/orcanet/announcements/1.0.0";
+/// How many announcements a subscriber can fall behind before it starts missing them.
+pub(crate) const ANNOUNCEMENT_CHANNEL_CAPACITY: usize = 1024;
+/// The largest gossipsub message we send or accept, the same as the gossipsub default.
+pub(crate) const MAX_TRANSMIT_SIZE: usize = 64 * 1024;
+/// The largest announcement we publish. The rest of the message needs room for the signature,
+/// the public key and the topic.
+const MAX_ANNOUNCEMENT_SIZE: usize = 60 * 1024;
+
+#[derive(Debug, Error)]
+pub(crate) enum AnnouncementError {
+    #[error("the announcement of the file info is {size} bytes, but at most {MAX_ANNOUNCEMENT_SIZE} bytes can be published")]
+    TooLarge { size: usize },
+}
+
+/// A file a peer started offering or changed the price of, sent to every receiver from
+/// [`crate::Peer::subscribe_announcements`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    /// The peer that published the announcement. Gossipsub checks the signature of the message,
+    /// so this is the peer that offers the file.
+    pub peer_id: PeerId,
+    pub file_info_hash: FileInfoHash,
+    pub file_info: FileInfo,
+    pub price: i64,
+}
+
+/// What goes over gossipsub. The peer id is taken from the source of the message and the file
+/// info hash is computed from the file info, so the sender can't claim either.
+#[derive(Debug, Serialize, Deserialize)]
+struct AnnouncementMessage {
+    file_info: FileInfo,
+    price: i64,
+}
+
+impl Announcement {
+    pub(crate) fn topic() -> IdentTopic {
+        IdentTopic::new(ANNOUNCEMENT_TOPIC)
+    }
+
+    /// Fails if the file info is too large to fit in a gossipsub message.
+    pub(crate) fn encode(supplier_info: &SupplierInfo) -> Result<Vec<u8>, AnnouncementError> {
+        let data = serde_json::to_vec(&AnnouncementMessage {
+            file_info: supplier_info.file_info.clone(),
+            price: supplier_info.user.price,
+        })
+        .expect("the announcement to serialize");
+        if data.len() > MAX_ANNOUNCEMENT_SIZE {
+            return Err(AnnouncementError::TooLarge { size: data.len() });
+        }
+        Ok(data)
+    }
+
+    pub(crate) fn decode(peer_id: PeerId, data: &[u8]) -> serde_json::Result<Self> {
+        let message: AnnouncementMessage = serde_json::from_slice(data)?;
+        Ok(Self {
+            peer_id,
+            file_info_hash: message.file_info.get_hash(),
+            file_info: message.file_info,
+            price: message.price,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use proto::market::User;
+
+    #[test]
+    fn test_announcement_roundtrip() {
+        let file_info = FileInfo {
+            file_hash: "foo".to_string(),
+            chunk_hashes: vec!["1".into(), "2".into()],
+            file_size: 8000,
+            file_name: "a_file".to_string(),
+        };
+        let file_info_hash = file_info.get_hash();
+        let user = User {
+            id: "416".to_string(),
+            name: "Alice".to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: 8080,
+            price: 100,
+        };
+        let peer_id = PeerId::random();
+        let data = Announcement::encode(&SupplierInfo {
+            file_info: file_info.clone(),
+            user: user.clone(),
+        })
+        .unwrap();
+        assert_eq!(
+            Announcement::decode(peer_id, &data).unwrap(),
+            Announcement {
+                peer_id,
+                file_info_hash,
+                file_info: file_info.clone(),
+                price: 100,
+            }
+        );
+        assert!(Announcement::decode(peer_id, b"not an announcement").is_err());
+
+        let too_large = FileInfo {
+            chunk_hashes: vec!["a".repeat(64); 1024],
+            ..file_info
+        };
+        assert!(matches!(
+            Announcement::encode(&SupplierInfo {
+                file_info: too_large,
+                user,
+            }),
+            Err(AnnouncementError::TooLarge { .. })
+        ));
+    }
+}
@@ -1,8 +1,11 @@
 use libp2p::{
+    allow_block_list::{AllowedPeers, Behaviour as AllowBlockListBehaviour, BlockedPeers},
     autonat::Behaviour as AutoNatBehaviour,
+    connection_limits::Behaviour as ConnectionLimitsBehaviour,
     dcutr::Behaviour as DcutrBehaviour,
+    gossipsub::Behaviour as GossipsubBehaviour,
     identify::Behaviour as IdentifyBehaviour,
-    kad::{store::MemoryStore, Behaviour as KadBehaviour},
+    kad::Behaviour as KadBehaviour,
     ping::Behaviour as PingBehaviour,
     relay::{client::Behaviour as RelayClientBehaviour, Behaviour as RelayServerBehaviour},
     request_response::cbor::Behaviour as CborReqResBehaviour,
@@ -10,18 +13,23 @@ use libp2p::{
 };
 use proto::market::FileInfoHash;
 
-use crate::lmm::{FileResponse, SupplierInfo};
-
-// TODO: maybe do somethign with toggle in future?
+use crate::{lmm::SignedFileResponse, store::MarketStore};
 
+// NOTE: the relay server, relay client and dcutr can be turned off through the config, and the
+// allow-list is only there if the config has allowed peers. The behaviours that deny connections
+// come first so that no other behaviour sets anything up for a connection that gets denied.
 #[derive(NetworkBehaviour)]
 pub(crate) struct Behaviour {
-    pub(crate) kad: KadBehaviour<MemoryStore>,
+    pub(crate) allowed_peers: Toggle<AllowBlockListBehaviour<AllowedPeers>>,
+    pub(crate) blocked_peers: AllowBlockListBehaviour<BlockedPeers>,
+    pub(crate) connection_limits: ConnectionLimitsBehaviour,
+    pub(crate) kad: KadBehaviour<MarketStore>,
     pub(crate) identify: IdentifyBehaviour,
     pub(crate) ping: PingBehaviour,
     pub(crate) autonat: AutoNatBehaviour,
     pub(crate) relay_server: Toggle<RelayServerBehaviour>,
     pub(crate) dcutr: Toggle<DcutrBehaviour>,
     pub(crate) relay_client: Toggle<RelayClientBehaviour>,
-    pub(crate) req_res: CborReqResBehaviour<FileInfoHash, FileResponse>,
+    pub(crate) req_res: CborReqResBehaviour<FileInfoHash, SignedFileResponse>,
+    pub(crate) gossipsub: GossipsubBehaviour,
 }
@@ -1,44 +1,123 @@
-use std::{net::Ipv4Addr, time::Duration};
+use std::{
+    collections::HashSet,
+    net::{Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
 
 use anyhow::Result;
 use futures::StreamExt;
-use libp2p::{multiaddr::Protocol, Multiaddr, Swarm};
-use log::{error, warn};
+use libp2p::{
+    core::transport::ListenerId, identity::Keypair, multiaddr::Protocol, Multiaddr, PeerId, Swarm,
+};
+use log::{error, info, warn};
 use tokio::{
     select,
-    sync::mpsc,
-    time::{interval, Interval},
+    sync::{broadcast, mpsc},
+    time::{interval, timeout, Interval},
 };
 
 use crate::{
+    announcement::Announcement,
     behaviour::Behaviour,
-    command::{Message, QueryHandler},
-    handler::{CommandRequestHandler, EventHandler, Handler},
-    lmm::LocalMarketMap,
-    BootNodes,
+    command::{request::Request, Message, QueryHandler},
+    handler::{
+        publish_file, CommandRequestHandler, EventHandler, Handler, HandlerContext, KadModeManager,
+    },
+    introspection::Introspection,
+    lmm::{LocalMarketMap, LMM_SWEEP_INTERVAL},
+    metrics::Metrics,
+    relays::{RelayManager, RELAY_MAINTENANCE_INTERVAL},
+    store::STORE_FLUSH_INTERVAL,
+    BootNodes, KadMode, MarketEvent, SuccessfulResponse, TransportKind,
 };
 
+/// How long we wait for the connections to close when shutting down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The parts of the [`crate::Config`] the coordinator needs to set up the network.
+pub(super) struct CoordinatorConfig {
+    pub(super) public_address: Option<Multiaddr>,
+    pub(super) boot_nodes: Option<BootNodes>,
+    pub(super) peer_tcp_port: u16,
+    pub(super) peer_quic_port: Option<u16>,
+    pub(super) bootstrap_time: Duration,
+    pub(super) relay_client: bool,
+    pub(super) transport: TransportKind,
+    /// Signs the supplier info we send to other peers.
+    pub(super) keypair: Keypair,
+    /// The Kademlia mode forced by the config.
+    pub(super) kad_mode: Option<KadMode>,
+    pub(super) blocked_peers: HashSet<PeerId>,
+}
+
 pub(super) struct Coordinator {
     query_handler: QueryHandler,
     swarm: Swarm<Behaviour>,
     lmm: LocalMarketMap,
     boot_nodes: Option<BootNodes>,
+    listeners: Vec<ListenerId>,
     command_receiver: mpsc::UnboundedReceiver<Message>,
+    context: HandlerContext,
     bootstrap_interval: Interval,
+    store_flush_interval: Interval,
+    lmm_sweep_interval: Interval,
+    relay_interval: Interval,
 }
 
 impl Coordinator {
     pub(crate) fn new(
         mut swarm: Swarm<Behaviour>,
-        public_address: Option<Multiaddr>,
-        boot_nodes: Option<BootNodes>,
-        peer_tcp_port: u16,
+        config: CoordinatorConfig,
         command_receiver: mpsc::UnboundedReceiver<Message>,
-        bootstrap_time: Duration,
+        events: broadcast::Sender<MarketEvent>,
+        announcements: broadcast::Sender<Announcement>,
+        lmm: LocalMarketMap,
     ) -> Result<Self> {
-        let listen_addr = Multiaddr::from(Protocol::Ip4(Ipv4Addr::UNSPECIFIED))
-            .with(Protocol::Tcp(peer_tcp_port));
-        swarm.listen_on(listen_addr)?;
+        let CoordinatorConfig {
+            public_address,
+            boot_nodes,
+            peer_tcp_port,
+            peer_quic_port,
+            bootstrap_time,
+            relay_client,
+            transport,
+            keypair,
+            kad_mode,
+            blocked_peers,
+        } = config;
+        let mut listeners = Vec::new();
+        let hosts = match transport {
+            TransportKind::Tcp => vec![
+                Protocol::Ip4(Ipv4Addr::UNSPECIFIED),
+                Protocol::Ip6(Ipv6Addr::UNSPECIFIED),
+            ],
+            TransportKind::Memory(port) => {
+                listeners.push(swarm.listen_on(Multiaddr::from(Protocol::Memory(port)))?);
+                vec![]
+            }
+        };
+        for host in hosts {
+            let mut listen_addrs =
+                vec![Multiaddr::from(host.clone()).with(Protocol::Tcp(peer_tcp_port))];
+            if let Some(peer_quic_port) = peer_quic_port {
+                listen_addrs.push(
+                    Multiaddr::from(host.clone())
+                        .with(Protocol::Udp(peer_quic_port))
+                        .with(Protocol::QuicV1),
+                );
+            }
+            for listen_addr in listen_addrs {
+                match swarm.listen_on(listen_addr.clone()) {
+                    Ok(listener_id) => listeners.push(listener_id),
+                    // NOTE: not every host has IPv6, so we only give up if IPv4 doesn't work
+                    Err(err) if matches!(host, Protocol::Ip6(_)) => {
+                        warn!("Failed to listen on {}: {}", listen_addr, err);
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+        let relays = RelayManager::new(relay_client, boot_nodes.as_ref());
         let boot_nodes = {
             if let Some(boot_nodes) = boot_nodes {
                 for (peer_id, addr) in boot_nodes.get_kad_addrs() {
@@ -57,13 +136,41 @@ impl Coordinator {
         if let Some(public_address) = public_address {
             swarm.add_external_address(public_address);
         }
+        let mut kad_mode = KadModeManager::new(kad_mode);
+        kad_mode.update(&mut swarm, &events);
+        swarm
+            .behaviour_mut()
+            .gossipsub
+            .subscribe(&Announcement::topic())?;
+        // NOTE: the files that were loaded back from disk still have to be advertised again
+        for (file_info_hash, file_info) in lmm.files() {
+            swarm
+                .behaviour_mut()
+                .kad
+                .start_providing(file_info_hash.clone().into_bytes().into())?;
+            publish_file(&mut swarm, file_info_hash, file_info);
+        }
         Ok(Self {
             boot_nodes,
-            lmm: Default::default(),
+            listeners,
+            lmm,
             query_handler: Default::default(),
             swarm,
             command_receiver,
+            context: HandlerContext {
+                relays,
+                events,
+                announcements,
+                metrics: Metrics::new(),
+                introspection: Introspection::default(),
+                keypair,
+                kad_mode,
+                blocked_peers,
+            },
             bootstrap_interval: interval(bootstrap_time),
+            store_flush_interval: interval(STORE_FLUSH_INTERVAL),
+            lmm_sweep_interval: interval(LMM_SWEEP_INTERVAL),
+            relay_interval: interval(RELAY_MAINTENANCE_INTERVAL),
         })
     }
 
@@ -75,25 +182,98 @@ impl Coordinator {
                         warn!("Failed to bootstrap: {}", err);
                     }
                 }
+                _ = self.store_flush_interval.tick() => {
+                    if let Err(err) = self.swarm.behaviour_mut().kad.store_mut().flush() {
+                        error!("Failed to flush the record store: {}", err);
+                    }
+                }
+                _ = self.lmm_sweep_interval.tick() => {
+                    for file_info_hash in self.lmm.remove_expired() {
+                        info!("File {} expired from the local market map", file_info_hash);
+                        self.swarm
+                            .behaviour_mut()
+                            .kad
+                            .stop_providing(&file_info_hash.into_bytes().into());
+                    }
+                }
+                _ = self.relay_interval.tick() => {
+                    self.context.relays.maintain(&mut self.swarm);
+                }
+                Some(qid) = self.query_handler.next_expired_query() => {
+                    warn!("Query {:?} hit its deadline", qid);
+                    if let Some(mut query) = self.swarm.behaviour_mut().kad.query_mut(&qid) {
+                        query.finish();
+                    }
+                }
                 event = self.swarm.select_next_some() => {
-                    let mut handler = Handler::new(&mut self.swarm, &mut self.lmm, &mut self.query_handler, self.boot_nodes.as_ref());
+                    let mut handler = Handler::new(&mut self.swarm, &mut self.lmm, &mut self.query_handler, self.boot_nodes.as_ref(), &mut self.context);
                     handler.handle_event(event);
                 }
                 command = self.command_receiver.recv() => {
-                    if let Some((request, responder)) = command {
-                        let mut handler = Handler::new(&mut self.swarm, &mut self.lmm, &mut self.query_handler, self.boot_nodes.as_ref());
-                        handler.handle_command(request, responder);
-                    } else {
-                        break;
+                    match command {
+                        Some((Request::Shutdown, responder)) => {
+                            self.shutdown().await;
+                            let _ = responder.send(Ok(SuccessfulResponse::Shutdown));
+                            break;
+                        }
+                        Some((request, responder)) => {
+                            let mut handler = Handler::new(&mut self.swarm, &mut self.lmm, &mut self.query_handler, self.boot_nodes.as_ref(), &mut self.context);
+                            handler.handle_command(request, responder);
+                        }
+                        None => {
+                            warn!("Coordinator receiver channel closed");
+                            self.shutdown().await;
+                            break;
+                        }
                     }
                 }
             }
         }
     }
+
+    /// Stops providing our files, closes the listeners and waits for the connections to other
+    /// peers to close before the swarm gets dropped.
+    async fn shutdown(&mut self) {
+        info!("Shutting down the coordinator");
+        for file_info_hash in self.lmm.file_info_hashes() {
+            self.swarm
+                .behaviour_mut()
+                .kad
+                .stop_providing(&file_info_hash.clone().into_bytes().into());
+        }
+        for listener_id in self.listeners.drain(..) {
+            self.swarm.remove_listener(listener_id);
+        }
+        self.context.relays.close_listeners(&mut self.swarm);
+        let peers: Vec<PeerId> = self.swarm.connected_peers().cloned().collect();
+        for peer_id in peers {
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+        }
+        let closed = timeout(SHUTDOWN_TIMEOUT, async {
+            while self.swarm.connected_peers().next().is_some() {
+                let event = self.swarm.select_next_some().await;
+                let mut handler = Handler::new(
+                    &mut self.swarm,
+                    &mut self.lmm,
+                    &mut self.query_handler,
+                    self.boot_nodes.as_ref(),
+                    &mut self.context,
+                );
+                handler.handle_event(event);
+            }
+        })
+        .await;
+        if closed.is_err() {
+            warn!("Not every connection closed in time, dropping the rest");
+        }
+        if let Err(err) = self.swarm.behaviour_mut().kad.store_mut().flush() {
+            error!("Failed to flush the record store: {}", err);
+        }
+    }
 }
 
 impl Drop for Coordinator {
     fn drop(&mut self) {
-        error!("Coordinator receiver channel closed");
+        info!("Coordinator shut down");
     }
 }
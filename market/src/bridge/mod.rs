@@ -1,22 +1,39 @@
 use std::{thread, time::Duration};
 
 use crate::{
+    announcement::{Announcement, ANNOUNCEMENT_CHANNEL_CAPACITY, MAX_TRANSMIT_SIZE},
     behaviour::Behaviour,
-    bridge::{coordinator::Coordinator, peer::Peer},
+    bridge::{
+        coordinator::{Coordinator, CoordinatorConfig},
+        peer::{CoordinatorHandle, Peer},
+    },
     command::Message,
-    Config,
+    event::{MarketEvent, EVENT_CHANNEL_CAPACITY},
+    handler::KadModeManager,
+    lmm::LocalMarketMap,
+    store::MarketStore,
+    Config, KeypairError, TransportKind,
 };
 use libp2p::{
-    autonat, dcutr, identify,
-    identity::{ed25519, Keypair},
-    kad::{self, store::MemoryStore, Mode, NoKnownPeers},
+    allow_block_list::{self, AllowedPeers, BlockedPeers},
+    autonat, connection_limits,
+    core::{
+        transport::{MemoryTransport, Transport},
+        upgrade,
+    },
+    dcutr, gossipsub, identify,
+    identity::Keypair,
+    kad::{self, NoKnownPeers},
     noise, ping, relay,
     request_response::{self, ProtocolSupport},
     swarm::behaviour::toggle::Toggle,
-    tls, yamux, StreamProtocol, SwarmBuilder,
+    tls, yamux, StreamProtocol, Swarm, SwarmBuilder,
 };
 use thiserror::Error;
-use tokio::{runtime::Runtime, sync::mpsc};
+use tokio::{
+    runtime,
+    sync::{broadcast, mpsc},
+};
 
 const PROVIDER_REPUBLICATION: Duration = Duration::from_secs(60 * 5);
 pub(crate) const IDENTIFY_PROTOCOL_VERSION: &str = "/orcanet/id/1.0.0";
@@ -24,122 +41,34 @@ pub(crate) const KAD_PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/orcan
 pub(crate) const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60 * 10);
 
 pub(crate) const FILE_REQ_RES_PROTOCOL: [(StreamProtocol, ProtocolSupport); 1] = [(
-    StreamProtocol::new("/file_req_res/1.0.0"),
+    StreamProtocol::new("/file_req_res/2.0.0"),
     ProtocolSupport::Full,
 )];
 
+/// Starts the coordinator on a new thread with its own runtime. The number of worker threads of
+/// the runtime is taken from [`Config::worker_threads`].
 pub fn spawn(config: Config) -> Result<Peer, BridgeError> {
-    let Config {
-        peer_tcp_port,
-        boot_nodes,
-        coordinator_thread_name,
-        file_ttl,
-        public_address,
-        bootstrap_time,
-    } = config;
-
-    // TODO: use the zeroize crate for zeroing memory after move of public/priv key
-    let keypair = Keypair::from(ed25519::Keypair::generate());
-
-    let swarm = SwarmBuilder::with_existing_identity(keypair.clone())
-        .with_tokio()
-        .with_tcp(
-            Default::default(),
-            (tls::Config::new, noise::Config::new),
-            yamux::Config::default,
-        )
-        .map_err(|err| BridgeError::Tcp(err.to_string()))?
-        .with_dns()
-        .map_err(|err| BridgeError::Dns(err.to_string()))?
-        .with_relay_client(
-            (tls::Config::new, noise::Config::new),
-            yamux::Config::default,
-        )
-        .map_err(|err| BridgeError::RelayClient(err.to_string()))?
-        .with_behaviour(|key, relay_client| {
-            let peer_id = key.public().to_peer_id();
-
-            let mut kad = {
-                let mut kad_config = kad::Config::default();
-                kad_config
-                    .set_protocol_names(vec![KAD_PROTOCOL_NAME])
-                    .set_provider_record_ttl(Some(file_ttl))
-                    .set_provider_publication_interval(Some(PROVIDER_REPUBLICATION));
-
-                kad::Behaviour::with_config(peer_id, MemoryStore::new(peer_id), kad_config)
-            };
-            // NOTE: either we set this public_addr here or by default always set the kademlia to server
-            // mode. Perhaps we may want to keep it autoamtic? so if a node wants to be a server we'll just
-            // have it be a public_address.
-            // if user doesn't provide a public_address (even if is ofre example some local_addr),
-            // then they'll always be in kademlia client mode and advertising a file won't do much
-            // for that peer? maybe will just use this small hack for now until we figure something
-            // out better. iirc this deosn't affect relay/nat stuff
-            kad.set_mode(Some(Mode::Server));
-            let identify = {
-                let config =
-                    identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_owned(), key.public());
-                identify::Behaviour::new(config)
-            };
-            let ping = {
-                let config = ping::Config::new();
-                ping::Behaviour::new(config)
-            };
-            let autonat = {
-                let config = autonat::Config {
-                    boot_delay: Duration::from_secs(3),
-                    ..Default::default()
-                };
-                autonat::Behaviour::new(peer_id, config)
-            };
-
-            let relay_server = {
-                let config = relay::Config::default();
-                let relay_server = relay::Behaviour::new(peer_id, config);
-                Toggle::from(Some(relay_server))
-            };
-            let relay_client = Toggle::from(Some(relay_client));
-            let dcutr = Toggle::from(Some(dcutr::Behaviour::new(peer_id)));
-            let req_res = {
-                let config = request_response::Config::default();
-                request_response::Behaviour::new(FILE_REQ_RES_PROTOCOL, config)
-            };
-            Behaviour {
-                kad,
-                identify,
-                ping,
-                autonat,
-                relay_client,
-                relay_server,
-                dcutr,
-                req_res,
-            }
-        })
-        .map_err(|_| BridgeError::Behaviour)?
-        .with_swarm_config(|config| config.with_idle_connection_timeout(TIMEOUT))
-        .build();
-    let (command_sender, command_receiver) = mpsc::unbounded_channel::<Message>();
-    let (peer_init_tx, peer_init_rx) = std::sync::mpsc::channel::<anyhow::Result<Peer>>();
-    thread::Builder::new()
-        .name(coordinator_thread_name)
+    let thread_name = config.coordinator_thread_name.clone();
+    let worker_threads = config.worker_threads;
+    // NOTE: dropping a runtime from within an async context panics, so only build it once nothing
+    // can fail on the caller's thread anymore
+    let (coordinator, peer) = prepare(config)?;
+    let mut runtime = runtime::Builder::new_multi_thread();
+    if let Some(worker_threads) = worker_threads {
+        runtime.worker_threads(worker_threads);
+    }
+    let runtime = runtime
+        .enable_all()
+        .build()
+        .map_err(|err| BridgeError::Runtime(err.to_string()))?;
+    let (peer_init_tx, peer_init_rx) = std::sync::mpsc::channel::<anyhow::Result<()>>();
+    let coordinator_thread = thread::Builder::new()
+        .name(thread_name)
         .spawn(move || {
-            // TODO: maybe allow in future allow user to pass in # of worker threads they want to use
-            // here
-            Runtime::new().unwrap().block_on(async move {
-                let peer_id = *swarm.local_peer_id();
-                let maybe_coordinator = Coordinator::new(
-                    swarm,
-                    public_address,
-                    boot_nodes,
-                    peer_tcp_port,
-                    command_receiver,
-                    bootstrap_time,
-                );
-                match maybe_coordinator {
+            runtime.block_on(async move {
+                match coordinator.start() {
                     Ok(coordinator) => {
-                        peer_init_tx
-                            .send(Ok(Peer::new(peer_id, command_sender, keypair)))
-                            .expect("send to succeed");
+                        peer_init_tx.send(Ok(())).expect("send to succeed");
                         drop(peer_init_tx);
                         coordinator.run().await;
                     }
@@ -153,13 +82,267 @@ pub fn spawn(config: Config) -> Result<Peer, BridgeError> {
     peer_init_rx
         .recv()
         .expect("to receive some kind of response for initializing a peer")
-        .map_err(|err| BridgeError::PeerInitializationFailed(err.to_string()))
+        .map_err(|err| BridgeError::PeerInitializationFailed(err.to_string()))?;
+    Ok(peer.finish(CoordinatorHandle::Thread(coordinator_thread)))
+}
+
+/// Starts the coordinator as a task on the runtime this is called from instead of on a thread of
+/// its own. [`Config::coordinator_thread_name`] and [`Config::worker_threads`] are ignored.
+pub async fn spawn_in_current_runtime(config: Config) -> Result<Peer, BridgeError> {
+    let (coordinator, peer) = prepare(config)?;
+    let coordinator = coordinator
+        .start()
+        .map_err(|err| BridgeError::PeerInitializationFailed(err.to_string()))?;
+    let coordinator_task = tokio::spawn(coordinator.run());
+    Ok(peer.finish(CoordinatorHandle::Task(coordinator_task)))
+}
+
+/// Builds the swarm and everything else the coordinator and the [`Peer`] need, without starting
+/// the coordinator yet.
+fn prepare(config: Config) -> Result<(PendingCoordinator, PendingPeer), BridgeError> {
+    let Config {
+        peer_tcp_port,
+        peer_quic_port,
+        boot_nodes,
+        coordinator_thread_name: _,
+        file_ttl,
+        public_address,
+        bootstrap_time,
+        keypair_source,
+        record_store,
+        local_market_map_path,
+        holder_timeout,
+        holder_concurrency,
+        kad_mode,
+        relay_server,
+        relay_client,
+        dcutr,
+        relay_limits,
+        transport,
+        worker_threads: _,
+        allowed_peers,
+        blocked_peers,
+        connection_limits,
+    } = config;
+
+    // TODO: use the zeroize crate for zeroing memory after move of public/priv key
+    let keypair = keypair_source.load()?;
+    let store = MarketStore::new(keypair.public().to_peer_id(), &record_store)
+        .map_err(|err| BridgeError::RecordStore(err.to_string()))?;
+    let lmm = match local_market_map_path {
+        Some(path) => LocalMarketMap::with_path(file_ttl, path)
+            .map_err(|err| BridgeError::LocalMarketMap(err.to_string()))?,
+        None => LocalMarketMap::new(file_ttl),
+    };
+
+    // NOTE: the block list behaviour can't be asked which peers it blocks
+    let coordinator_blocked_peers = blocked_peers.clone();
+    let behaviour = move |key: &Keypair, relay_client_behaviour: relay::client::Behaviour| {
+        let peer_id = key.public().to_peer_id();
+
+        let allowed_peers = Toggle::from(allowed_peers.map(|allowed_peers| {
+            let mut behaviour = allow_block_list::Behaviour::<AllowedPeers>::default();
+            for peer in allowed_peers {
+                behaviour.allow_peer(peer);
+            }
+            behaviour
+        }));
+        let blocked_peers = {
+            let mut behaviour = allow_block_list::Behaviour::<BlockedPeers>::default();
+            for peer in blocked_peers {
+                behaviour.block_peer(peer);
+            }
+            behaviour
+        };
+        let connection_limits =
+            connection_limits::Behaviour::new(connection_limits.into_connection_limits());
+
+        let mut kad = {
+            let mut kad_config = kad::Config::default();
+            kad_config
+                .set_protocol_names(vec![KAD_PROTOCOL_NAME])
+                .set_provider_record_ttl(Some(file_ttl))
+                .set_provider_publication_interval(Some(PROVIDER_REPUBLICATION));
+
+            kad::Behaviour::with_config(peer_id, store, kad_config)
+        };
+        // NOTE: without an override, the coordinator switches Kademlia to server mode once a
+        // direct external address is confirmed, so peers behind a NAT stay in client mode
+        kad.set_mode(Some(KadModeManager::new(kad_mode).mode()));
+        let identify = {
+            let config = identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_owned(), key.public());
+            identify::Behaviour::new(config)
+        };
+        let ping = {
+            let config = ping::Config::new();
+            ping::Behaviour::new(config)
+        };
+        let autonat = {
+            let config = autonat::Config {
+                boot_delay: Duration::from_secs(3),
+                ..Default::default()
+            };
+            autonat::Behaviour::new(peer_id, config)
+        };
+
+        let relay_server = Toggle::from(
+            relay_server.then(|| relay::Behaviour::new(peer_id, relay_limits.into_relay_config())),
+        );
+        let relay_client = Toggle::from(relay_client.then_some(relay_client_behaviour));
+        let dcutr = Toggle::from(dcutr.then(|| dcutr::Behaviour::new(peer_id)));
+        let req_res = {
+            let config = request_response::Config::default();
+            request_response::Behaviour::new(FILE_REQ_RES_PROTOCOL, config)
+        };
+        let gossipsub = {
+            let config = gossipsub::ConfigBuilder::default()
+                .max_transmit_size(MAX_TRANSMIT_SIZE)
+                .build()
+                .expect("the gossipsub config to be valid");
+            gossipsub::Behaviour::new(gossipsub::MessageAuthenticity::Signed(key.clone()), config)
+                .expect("the gossipsub config to be valid")
+        };
+        Behaviour {
+            allowed_peers,
+            blocked_peers,
+            connection_limits,
+            kad,
+            identify,
+            ping,
+            autonat,
+            relay_client,
+            relay_server,
+            dcutr,
+            req_res,
+            gossipsub,
+        }
+    };
+    let swarm = match transport {
+        TransportKind::Tcp => SwarmBuilder::with_existing_identity(keypair.clone())
+            .with_tokio()
+            .with_tcp(
+                Default::default(),
+                (tls::Config::new, noise::Config::new),
+                yamux::Config::default,
+            )
+            .map_err(|err| BridgeError::Tcp(err.to_string()))?
+            .with_quic()
+            .with_dns()
+            .map_err(|err| BridgeError::Dns(err.to_string()))?
+            .with_relay_client(
+                (tls::Config::new, noise::Config::new),
+                yamux::Config::default,
+            )
+            .map_err(|err| BridgeError::RelayClient(err.to_string()))?
+            .with_behaviour(behaviour)
+            .map_err(|_| BridgeError::Behaviour)?
+            .with_swarm_config(|config| config.with_idle_connection_timeout(TIMEOUT))
+            .build(),
+        TransportKind::Memory(_) => SwarmBuilder::with_existing_identity(keypair.clone())
+            .with_tokio()
+            .with_other_transport(|key| {
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
+                    MemoryTransport::default()
+                        .upgrade(upgrade::Version::V1)
+                        .authenticate(noise::Config::new(key)?)
+                        .multiplex(yamux::Config::default()),
+                )
+            })
+            .map_err(|err| BridgeError::Memory(err.to_string()))?
+            .with_relay_client(noise::Config::new, yamux::Config::default)
+            .map_err(|err| BridgeError::RelayClient(err.to_string()))?
+            .with_behaviour(behaviour)
+            .map_err(|_| BridgeError::Behaviour)?
+            .with_swarm_config(|config| config.with_idle_connection_timeout(TIMEOUT))
+            .build(),
+    };
+    let (command_sender, command_receiver) = mpsc::unbounded_channel::<Message>();
+    let (event_sender, _) = broadcast::channel::<MarketEvent>(EVENT_CHANNEL_CAPACITY);
+    let (announcement_sender, _) =
+        broadcast::channel::<Announcement>(ANNOUNCEMENT_CHANNEL_CAPACITY);
+    let coordinator = PendingCoordinator {
+        swarm,
+        config: CoordinatorConfig {
+            public_address,
+            boot_nodes,
+            peer_tcp_port,
+            peer_quic_port,
+            bootstrap_time,
+            relay_client,
+            transport,
+            keypair: keypair.clone(),
+            kad_mode,
+            blocked_peers: coordinator_blocked_peers,
+        },
+        command_receiver,
+        events: event_sender.clone(),
+        announcements: announcement_sender.clone(),
+        lmm,
+    };
+    let peer = PendingPeer {
+        command_sender,
+        events: event_sender,
+        announcements: announcement_sender,
+        keypair,
+        holder_timeout,
+        holder_concurrency,
+    };
+    Ok((coordinator, peer))
+}
+
+struct PendingCoordinator {
+    swarm: Swarm<Behaviour>,
+    config: CoordinatorConfig,
+    command_receiver: mpsc::UnboundedReceiver<Message>,
+    events: broadcast::Sender<MarketEvent>,
+    announcements: broadcast::Sender<Announcement>,
+    lmm: LocalMarketMap,
+}
+
+impl PendingCoordinator {
+    /// Has to be called from within a runtime since the listeners and intervals are set up here.
+    fn start(self) -> anyhow::Result<Coordinator> {
+        Coordinator::new(
+            self.swarm,
+            self.config,
+            self.command_receiver,
+            self.events,
+            self.announcements,
+            self.lmm,
+        )
+    }
+}
+
+struct PendingPeer {
+    command_sender: mpsc::UnboundedSender<Message>,
+    events: broadcast::Sender<MarketEvent>,
+    announcements: broadcast::Sender<Announcement>,
+    keypair: Keypair,
+    holder_timeout: Duration,
+    holder_concurrency: usize,
+}
+
+impl PendingPeer {
+    fn finish(self, coordinator: CoordinatorHandle) -> Peer {
+        Peer::new(
+            self.keypair.public().to_peer_id(),
+            self.command_sender,
+            self.events,
+            self.announcements,
+            self.keypair,
+            coordinator,
+            self.holder_timeout,
+            self.holder_concurrency,
+        )
+    }
 }
 
 #[derive(Debug, Clone, Error)]
 pub enum BridgeError {
     #[error("TCP failed to initialize: {0}")]
     Tcp(String),
+    #[error("Memory transport failed to initialize: {0}")]
+    Memory(String),
     #[error("DNS failed to initialize: {0}")]
     Dns(String),
     #[error("Relay client failed to initialize: {0}")]
@@ -172,6 +355,14 @@ pub enum BridgeError {
     InitialListen(String),
     #[error("Booting failed {0}!")]
     Booting(String),
+    #[error("Runtime failed to start: {0}")]
+    Runtime(String),
+    #[error("Keypair failed to load: {0}")]
+    Keypair(#[from] KeypairError),
+    #[error("Record store failed to load: {0}")]
+    RecordStore(String),
+    #[error("Local market map failed to load: {0}")]
+    LocalMarketMap(String),
     #[error("Peer initialization failed!")]
     PeerInitializationFailed(String),
 }
@@ -1,42 +1,89 @@
+use std::collections::{HashSet, VecDeque};
+use std::num::NonZeroUsize;
+use std::pin::pin;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use futures::future::{self, BoxFuture};
+use futures::stream::{self, FuturesUnordered};
+use futures::{FutureExt, Stream, StreamExt};
 use libp2p::identity::Keypair;
-use libp2p::PeerId;
+use libp2p::{Multiaddr, PeerId};
+use log::warn;
 use proto::market::FileInfo;
 use proto::market::FileInfoHash;
 use proto::market::HoldersResponse;
 use proto::market::User;
+use tokio::select;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
+use tokio::task;
+use tokio::time::timeout;
 
+use crate::announcement::Announcement;
 use crate::command::request::KadRequest;
 use crate::command::request::LmmRequest;
 use crate::command::request::ReqResRequest;
 use crate::command::Message;
+use crate::keyword;
 use crate::FailureResponse;
 use crate::FileResponse;
+use crate::HolderFailure;
+use crate::HolderFailureReason;
 use crate::KadSuccessfulResponse;
 use crate::LmmSuccessfulResponse;
+use crate::MarketEvent;
 use crate::ReqResSuccessfulResponse;
+use crate::SearchResult;
 use crate::SuccessfulResponse;
+use crate::SupplierInfo;
 use crate::{command::request::Request, Response};
 
+/// Where the coordinator of a [`Peer`] runs.
+#[derive(Debug)]
+pub(crate) enum CoordinatorHandle {
+    /// On a thread with its own runtime, see [`crate::bridge::spawn`].
+    Thread(thread::JoinHandle<()>),
+    /// As a task on the caller's runtime, see [`crate::bridge::spawn_in_current_runtime`].
+    Task(task::JoinHandle<()>),
+}
+
 #[derive(Debug)]
 pub struct Peer {
     peer_id: PeerId,
     sender: mpsc::UnboundedSender<Message>,
+    events: broadcast::Sender<MarketEvent>,
+    announcements: broadcast::Sender<Announcement>,
     keypair: Keypair,
+    coordinator: Mutex<Option<CoordinatorHandle>>,
+    holder_timeout: Duration,
+    holder_concurrency: usize,
 }
 
 impl Peer {
+    #[allow(clippy::too_many_arguments)]
     #[inline(always)]
     pub(crate) const fn new(
         peer_id: PeerId,
         sender: mpsc::UnboundedSender<Message>,
+        events: broadcast::Sender<MarketEvent>,
+        announcements: broadcast::Sender<Announcement>,
         keypair: Keypair,
+        coordinator: CoordinatorHandle,
+        holder_timeout: Duration,
+        holder_concurrency: usize,
     ) -> Self {
         Self {
             peer_id,
             sender,
+            events,
+            announcements,
             keypair,
+            coordinator: Mutex::new(Some(coordinator)),
+            holder_timeout,
+            holder_concurrency,
         }
     }
 
@@ -50,6 +97,21 @@ impl Peer {
         &self.keypair
     }
 
+    /// Subscribes to the [`MarketEvent`]s of this peer. Only the events that happen after
+    /// subscribing are received, and a receiver that falls too far behind misses the oldest ones.
+    #[inline(always)]
+    pub fn subscribe(&self) -> broadcast::Receiver<MarketEvent> {
+        self.events.subscribe()
+    }
+
+    /// Subscribes to the [`Announcement`]s other peers publish when they register a file or change
+    /// its price. Our own announcements aren't received. Like [`Peer::subscribe`], only what is
+    /// announced after subscribing is received.
+    #[inline(always)]
+    pub fn subscribe_announcements(&self) -> broadcast::Receiver<Announcement> {
+        self.announcements.subscribe()
+    }
+
     #[inline(always)]
     async fn send(&self, request: Request) -> Response {
         let (tx, rx) = oneshot::channel();
@@ -74,6 +136,110 @@ impl Peer {
         self.send(Request::ConnectedTo { peer_id }).await
     }
 
+    /// Dials `address` and answers with the peer we connected to once the connection is
+    /// established. If the address ends with `/p2p/<peer id>`, connecting to any other peer fails.
+    #[inline(always)]
+    pub async fn dial(&self, address: Multiaddr) -> Response {
+        self.send(Request::Dial { address }).await
+    }
+
+    /// Adds a known address of `peer_id` to the Kademlia routing table, so it can be dialed and
+    /// found by queries without it being a boot node.
+    #[inline(always)]
+    pub async fn add_peer_address(&self, peer_id: PeerId, address: Multiaddr) -> Response {
+        self.send(Request::AddPeerAddress { peer_id, address })
+            .await
+    }
+
+    /// Closes every connection with `peer_id`. Fails if we aren't connected to it.
+    #[inline(always)]
+    pub async fn disconnect(&self, peer_id: PeerId) -> Response {
+        self.send(Request::Disconnect { peer_id }).await
+    }
+
+    /// Closes every connection with `peer_id`, refuses new ones and removes the peer from the
+    /// Kademlia routing table until it is unblocked.
+    #[inline(always)]
+    pub async fn block_peer(&self, peer_id: PeerId) -> Response {
+        self.send(Request::BlockPeer { peer_id }).await
+    }
+
+    /// Lets a peer blocked by [`Peer::block_peer`] or the config connect again. It is added back
+    /// to the routing table once it is connected.
+    #[inline(always)]
+    pub async fn unblock_peer(&self, peer_id: PeerId) -> Response {
+        self.send(Request::UnblockPeer { peer_id }).await
+    }
+
+    /// Stops providing our files, closes the listeners, disconnects from every peer and waits for
+    /// the coordinator to finish. Every request made after this fails with a
+    /// [`FailureResponse::SendError`].
+    pub async fn shutdown(&self) -> Response {
+        let coordinator = self
+            .coordinator
+            .lock()
+            .expect("the lock to not be poisoned")
+            .take();
+        let Some(coordinator) = coordinator else {
+            return Ok(SuccessfulResponse::Shutdown);
+        };
+        let res = self.send(Request::Shutdown).await;
+        match coordinator {
+            CoordinatorHandle::Thread(coordinator_thread) => {
+                task::spawn_blocking(move || coordinator_thread.join())
+                    .await
+                    .map_err(|err| FailureResponse::ShutdownError(err.to_string()))?
+                    .map_err(|_| {
+                        FailureResponse::ShutdownError("The coordinator thread panicked".to_owned())
+                    })?;
+            }
+            CoordinatorHandle::Task(coordinator_task) => {
+                coordinator_task
+                    .await
+                    .map_err(|err| FailureResponse::ShutdownError(err.to_string()))?;
+            }
+        }
+        res
+    }
+
+    /// Whether AutoNAT found us to be publicly reachable. Until enough probes were made, the status
+    /// is [`crate::NatStatus::Unknown`].
+    #[inline(always)]
+    pub async fn nat_status(&self) -> Response {
+        self.send(Request::NatStatus).await
+    }
+
+    /// The Kademlia k-buckets that aren't empty together with the addresses of their peers.
+    #[inline(always)]
+    pub async fn routing_table(&self) -> Response {
+        self.send(Request::RoutingTable).await
+    }
+
+    /// Every open connection with its endpoint, whether it is relayed and its last ping.
+    #[inline(always)]
+    pub async fn connections(&self) -> Response {
+        self.send(Request::Connections).await
+    }
+
+    /// The confirmed external addresses and the candidates that weren't confirmed yet.
+    #[inline(always)]
+    pub async fn external_addresses(&self) -> Response {
+        self.send(Request::ExternalAddresses).await
+    }
+
+    /// A snapshot of the routing table size, the connections, the Kademlia queries, the holder
+    /// requests, the relay reservations and the local market map size.
+    #[inline(always)]
+    pub async fn metrics(&self) -> Response {
+        self.send(Request::Metrics).await
+    }
+
+    /// The same metrics as [`Peer::metrics`] in the Prometheus text format.
+    #[inline(always)]
+    pub async fn prometheus_metrics(&self) -> Response {
+        self.send(Request::PrometheusMetrics).await
+    }
+
     #[inline(always)]
     pub async fn get_closest_peers(&self, key: impl Into<Vec<u8>>) -> Response {
         self.send(Request::Kad(KadRequest::GetClosestPeers {
@@ -82,19 +248,26 @@ impl Peer {
         .await
     }
 
-    #[inline(always)]
-    pub async fn is_local_file_owner(&self, file_info_hash: impl Into<FileInfoHash>) -> bool {
-        if let Ok(SuccessfulResponse::LmmResponse(LmmSuccessfulResponse::IsLocalFileOwner {
-            is_owner,
-        })) = self
+    /// Whether we registered the file and it hasn't expired. Fails like every other request once
+    /// the coordinator is shut down.
+    pub async fn is_local_file_owner(
+        &self,
+        file_info_hash: impl Into<FileInfoHash>,
+    ) -> Result<bool, FailureResponse> {
+        let file_info_hash: FileInfoHash = file_info_hash.into();
+        match self
             .send(Request::LocalMarketMap(LmmRequest::IsLocalFileOwner {
-                file_info_hash: file_info_hash.into(),
+                file_info_hash: file_info_hash.clone(),
             }))
-            .await
+            .await?
         {
-            is_owner
-        } else {
-            panic!("This should never run since there is no error ever sent back.")
+            SuccessfulResponse::LmmResponse(LmmSuccessfulResponse::IsLocalFileOwner {
+                is_owner,
+            }) => Ok(is_owner),
+            res => {
+                warn!("Unexpected response to the owner check of {file_info_hash}: {res:?}");
+                Ok(false)
+            }
         }
     }
 
@@ -111,11 +284,54 @@ impl Peer {
         .await
     }
 
+    /// Looks up the [`FileInfo`] that every peer registering the file stores in the DHT. Records
+    /// that don't hash to `file_info_hash` are ignored.
+    #[inline(always)]
+    pub async fn get_file_info(&self, file_info_hash: impl Into<FileInfoHash>) -> Response {
+        self.send(Request::Kad(KadRequest::GetFileInfo {
+            file_info_hash: file_info_hash.into(),
+        }))
+        .await
+    }
+
+    /// Like [`Peer::get_file_info`], but a failed lookup is only logged.
+    async fn get_file_info_record(&self, file_info_hash: FileInfoHash) -> Option<FileInfo> {
+        match self.get_file_info(file_info_hash.clone()).await {
+            Ok(SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetFileInfo {
+                file_info,
+            })) => file_info,
+            Ok(res) => {
+                warn!("Unexpected response to the file info lookup of {file_info_hash}: {res:?}");
+                None
+            }
+            Err(err) => {
+                warn!("Could not look up the file info of {file_info_hash}: {err}");
+                None
+            }
+        }
+    }
+
     pub async fn get_providers(&self, file_info_hash: impl Into<FileInfoHash>) -> Response {
+        self.get_providers_until(file_info_hash, None, None).await
+    }
+
+    /// Gets the providers found by every step of the Kademlia query. The query is cut short once
+    /// `quorum` providers were found or the `deadline` passed, in which case the providers found
+    /// so far are returned.
+    pub async fn get_providers_until(
+        &self,
+        file_info_hash: impl Into<FileInfoHash>,
+        quorum: Option<NonZeroUsize>,
+        deadline: Option<Duration>,
+    ) -> Response {
         let file_info_hash: FileInfoHash = file_info_hash.into();
-        let is_local_file_owner = self.is_local_file_owner(file_info_hash.clone()).await;
+        let is_local_file_owner = self.is_local_file_owner(file_info_hash.clone()).await?;
         let mut res = self
-            .send(Request::Kad(KadRequest::GetProviders { file_info_hash }))
+            .send(Request::Kad(KadRequest::GetProviders {
+                file_info_hash,
+                quorum: quorum.map(NonZeroUsize::get),
+                deadline,
+            }))
             .await;
         if let Ok(SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetProviders {
             ref mut providers,
@@ -128,40 +344,202 @@ impl Peer {
         res
     }
 
-    #[inline(always)]
+    /// Finds the providers of the file and asks each of them for their [`SupplierInfo`] at the same
+    /// time, up to the configured concurrency. Providers that don't answer within the holder
+    /// timeout, or answer without the file, are reported in `failures`. The file info is looked
+    /// up in the DHT at the same time, so it is there even if no holder answers.
+    ///
+    /// [`SupplierInfo`]: crate::SupplierInfo
     pub async fn check_holders(&self, file_info_hash: impl Into<FileInfoHash>) -> Response {
         let file_info_hash: FileInfoHash = file_info_hash.into();
-        let res = self.get_providers(file_info_hash.clone()).await;
-        if let Ok(SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetProviders {
-            providers,
-        })) = res
-        {
-            let mut holders = Vec::new();
-            let mut file_info = None;
-            for provider in providers {
-                // TODO: can optimize this but lazy for now
-                let maybe_holder = self
-                    .get_holder_by_peer_id(provider, file_info_hash.clone())
-                    .await;
-                if let Ok(SuccessfulResponse::ReqResResponse(
-                    ReqResSuccessfulResponse::GetHolderByPeerId {
-                        holder: FileResponse::HasFile(holder),
-                    },
-                )) = maybe_holder
-                {
+        let (res, file_info_record) = future::join(
+            self.get_providers(file_info_hash.clone()),
+            self.get_file_info_record(file_info_hash.clone()),
+        )
+        .await;
+        let providers = match res {
+            Ok(SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetProviders {
+                providers,
+            })) => providers,
+            // NOTE: the file info is still worth returning if no provider could be found
+            Err(_) if file_info_record.is_some() => Vec::new(),
+            res => return res,
+        };
+        let results = stream::iter(providers)
+            .map(|provider| self.resolve_holder(provider, file_info_hash.clone()))
+            .buffered(self.holder_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        let mut holders = Vec::new();
+        let mut file_info = file_info_record;
+        let mut failures = Vec::new();
+        for res in results {
+            match res {
+                Ok(holder) => {
                     if file_info.is_none() {
                         file_info = Some(holder.file_info);
                     }
                     holders.push(holder.user);
                 }
+                Err(failure) => failures.push(failure),
             }
-            Ok(SuccessfulResponse::CheckHolders(HoldersResponse {
-                file_info,
-                holders,
-            }))
-        } else {
-            res
         }
+        Ok(SuccessfulResponse::CheckHolders {
+            response: HoldersResponse { file_info, holders },
+            failures,
+        })
+    }
+
+    /// Like [`Peer::check_holders`], but every holder is yielded as soon as it answers instead of
+    /// waiting for all of them. Providers found by later steps of the Kademlia query are asked as
+    /// they come in. The stream ends once the query is done and every provider has answered or
+    /// timed out.
+    pub async fn check_holders_stream(
+        &self,
+        file_info_hash: impl Into<FileInfoHash>,
+    ) -> Result<impl Stream<Item = SupplierInfo> + '_, FailureResponse> {
+        let file_info_hash: FileInfoHash = file_info_hash.into();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        if self.is_local_file_owner(file_info_hash.clone()).await? {
+            let _ = sender.send(vec![*self.peer_id()]);
+        }
+        self.send(Request::Kad(KadRequest::GetProvidersStream {
+            file_info_hash: file_info_hash.clone(),
+            sender,
+        }))
+        .await?;
+        let state = HolderStream {
+            providers: receiver,
+            providers_done: false,
+            seen: HashSet::new(),
+            queued: VecDeque::new(),
+            pending: FuturesUnordered::new(),
+        };
+        Ok(stream::unfold(state, move |mut state| {
+            let file_info_hash = file_info_hash.clone();
+            async move {
+                loop {
+                    while state.pending.len() < self.holder_concurrency {
+                        let Some(provider) = state.queued.pop_front() else {
+                            break;
+                        };
+                        state.pending.push(
+                            self.resolve_holder(provider, file_info_hash.clone())
+                                .boxed(),
+                        );
+                    }
+                    if state.providers_done && state.pending.is_empty() {
+                        return None;
+                    }
+                    select! {
+                        providers = state.providers.recv(), if !state.providers_done => {
+                            match providers {
+                                Some(providers) => {
+                                    for provider in providers {
+                                        if state.seen.insert(provider) {
+                                            state.queued.push_back(provider);
+                                        }
+                                    }
+                                }
+                                None => state.providers_done = true,
+                            }
+                        }
+                        Some(res) = state.pending.next(), if !state.pending.is_empty() => {
+                            match res {
+                                Ok(holder) => return Some((holder, state)),
+                                Err(failure) => {
+                                    warn!(
+                                        "Could not get holder info from {}: {}",
+                                        failure.peer_id, failure.reason
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Asks a single provider for its [`SupplierInfo`], giving up after the holder timeout.
+    async fn resolve_holder(
+        &self,
+        provider: PeerId,
+        file_info_hash: FileInfoHash,
+    ) -> Result<SupplierInfo, HolderFailure> {
+        let res = timeout(
+            self.holder_timeout,
+            self.get_holder_by_peer_id(provider, file_info_hash),
+        )
+        .await;
+        let reason = match res {
+            Ok(Ok(SuccessfulResponse::ReqResResponse(
+                ReqResSuccessfulResponse::GetHolderByPeerId {
+                    holder: FileResponse::HasFile(holder),
+                },
+            ))) => return Ok(holder),
+            Ok(Ok(_)) => HolderFailureReason::NoFile,
+            Ok(Err(err)) => HolderFailureReason::Failed(err),
+            Err(_) => HolderFailureReason::Timeout,
+        };
+        Err(HolderFailure {
+            peer_id: provider,
+            reason,
+        })
+    }
+
+    /// Finds the files whose names share keywords with the query, most matching keywords first.
+    /// The [`FileInfo`] of every file is looked up in the DHT and else asked from its holders, so
+    /// files without either are left out. Fails only if the lookup of every keyword failed.
+    pub async fn search(&self, query: &str) -> Response {
+        let lookups = future::join_all(
+            keyword::keywords(query)
+                .into_iter()
+                .map(|keyword| self.send(Request::Kad(KadRequest::GetKeyword { keyword }))),
+        )
+        .await;
+        let mut found = Vec::new();
+        let mut failure = None;
+        for res in lookups {
+            match res {
+                Ok(SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetKeyword {
+                    file_info_hashes,
+                })) => found.push(file_info_hashes),
+                Ok(res) => warn!("Unexpected response to a keyword lookup: {res:?}"),
+                Err(err) => {
+                    warn!("Could not look up a keyword: {err}");
+                    failure = Some(err);
+                }
+            }
+        }
+        if let Some(failure) = failure.filter(|_| found.is_empty()) {
+            return Err(failure);
+        }
+        let results = stream::iter(keyword::rank(found))
+            .map(|(file_info_hash, matching_keywords)| async move {
+                let file_info = self.find_file_info(file_info_hash.clone()).await?;
+                Some(SearchResult {
+                    file_info_hash,
+                    file_info,
+                    matching_keywords,
+                })
+            })
+            .buffered(self.holder_concurrency)
+            .filter_map(future::ready)
+            .collect()
+            .await;
+        Ok(SuccessfulResponse::Search { results })
+    }
+
+    /// The [`FileInfo`] from the DHT record of the file, or else from the first holder of the file
+    /// that answers.
+    async fn find_file_info(&self, file_info_hash: FileInfoHash) -> Option<FileInfo> {
+        if let Some(file_info) = self.get_file_info_record(file_info_hash.clone()).await {
+            return Some(file_info);
+        }
+        let holders = self.check_holders_stream(file_info_hash).await.ok()?;
+        let mut holders = pin!(holders);
+        holders.next().await.map(|holder| holder.file_info)
     }
 
     #[inline(always)]
@@ -178,4 +556,33 @@ impl Peer {
         }))
         .await
     }
+
+    /// Changes the price of a file we registered and announces the new price. Fails if the file
+    /// isn't registered or expired.
+    #[inline(always)]
+    pub async fn set_price(&self, file_info_hash: impl Into<FileInfoHash>, price: i64) -> Response {
+        self.send(Request::SetPrice {
+            file_info_hash: file_info_hash.into(),
+            price,
+        })
+        .await
+    }
+
+    /// Stops providing the file and removes it from the local market map, so this peer answers
+    /// [`FileResponse::NoFile`] for it from now on.
+    #[inline(always)]
+    pub async fn unregister_file(&self, file_info_hash: impl Into<FileInfoHash>) -> Response {
+        self.send(Request::Kad(KadRequest::UnregisterFile {
+            file_info_hash: file_info_hash.into(),
+        }))
+        .await
+    }
+}
+
+struct HolderStream<'a> {
+    providers: mpsc::UnboundedReceiver<Vec<PeerId>>,
+    providers_done: bool,
+    seen: HashSet<PeerId>,
+    queued: VecDeque<PeerId>,
+    pending: FuturesUnordered<BoxFuture<'a, Result<SupplierInfo, HolderFailure>>>,
 }
@@ -3,15 +3,43 @@ use crate::{
     handler::{send_err, send_ok},
     Response,
 };
+use futures::future::poll_fn;
+use libp2p::{kad::QueryId, request_response::OutboundRequestId, PeerId};
 use log::error;
-use std::collections::HashMap;
-use tokio::sync::oneshot;
+use proto::market::FileInfoHash;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::time::{delay_queue, DelayQueue};
 
 pub(crate) type Message = (Request, oneshot::Sender<Response>);
 
 #[derive(Debug, Default)]
 pub(crate) struct QueryHandler {
     inner: HashMap<Query, oneshot::Sender<Response>>,
+    provider_streams: HashMap<QueryId, mpsc::UnboundedSender<Vec<PeerId>>>,
+    provider_queries: HashMap<QueryId, ProviderCollector>,
+    query_deadlines: DelayQueue<QueryId>,
+    holder_requests: HashMap<OutboundRequestId, FileInfoHash>,
+    keyword_queries: HashMap<QueryId, KeywordCollector>,
+    file_info_queries: HashSet<QueryId>,
+}
+
+/// The files found so far by a GetRecord query for a keyword.
+#[derive(Debug)]
+struct KeywordCollector {
+    keyword: String,
+    file_info_hashes: Vec<FileInfoHash>,
+}
+
+/// The providers found so far by a GetProviders query that is answered once it's done.
+#[derive(Debug)]
+struct ProviderCollector {
+    providers: Vec<PeerId>,
+    quorum: Option<usize>,
+    deadline: Option<delay_queue::Key>,
 }
 
 impl QueryHandler {
@@ -32,6 +60,180 @@ impl QueryHandler {
             }
         }
     }
+
+    pub(crate) fn add_provider_query(
+        &mut self,
+        qid: QueryId,
+        responder: oneshot::Sender<Response>,
+        quorum: Option<usize>,
+        deadline: Option<Duration>,
+    ) {
+        let deadline = deadline.map(|deadline| self.query_deadlines.insert(qid, deadline));
+        self.provider_queries.insert(
+            qid,
+            ProviderCollector {
+                providers: Vec::new(),
+                quorum,
+                deadline,
+            },
+        );
+        self.add_query(Query::Kad(qid), responder);
+    }
+
+    /// Adds the providers found by a step of the query, skipping the ones we already know about.
+    /// Returns `true` once the quorum of the query has been reached.
+    pub(crate) fn collect_providers(
+        &mut self,
+        qid: &QueryId,
+        providers: impl IntoIterator<Item = PeerId>,
+    ) -> bool {
+        let Some(collector) = self.provider_queries.get_mut(qid) else {
+            return false;
+        };
+        for provider in providers {
+            if !collector.providers.contains(&provider) {
+                collector.providers.push(provider);
+            }
+        }
+        collector
+            .quorum
+            .is_some_and(|quorum| collector.providers.len() >= quorum)
+    }
+
+    pub(crate) fn take_providers(&mut self, qid: &QueryId) -> Vec<PeerId> {
+        match self.provider_queries.remove(qid) {
+            Some(collector) => {
+                if let Some(deadline) = collector.deadline {
+                    self.query_deadlines.remove(&deadline);
+                }
+                collector.providers
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Resolves with the next query whose deadline passed. Resolves with `None` right away if no
+    /// query has a deadline.
+    pub(crate) async fn next_expired_query(&mut self) -> Option<QueryId> {
+        let qid = poll_fn(|cx| self.query_deadlines.poll_expired(cx))
+            .await?
+            .into_inner();
+        if let Some(collector) = self.provider_queries.get_mut(&qid) {
+            collector.deadline = None;
+        }
+        Some(qid)
+    }
+
+    /// Remembers which file we asked the holder about, so its answer can be checked against it.
+    pub(crate) fn add_holder_request(
+        &mut self,
+        request_id: OutboundRequestId,
+        file_info_hash: FileInfoHash,
+        responder: oneshot::Sender<Response>,
+    ) {
+        self.holder_requests.insert(request_id, file_info_hash);
+        self.add_query(Query::ReqRes(request_id), responder);
+    }
+
+    pub(crate) fn take_holder_request(
+        &mut self,
+        request_id: &OutboundRequestId,
+    ) -> Option<FileInfoHash> {
+        self.holder_requests.remove(request_id)
+    }
+
+    pub(crate) fn add_keyword_query(
+        &mut self,
+        qid: QueryId,
+        keyword: String,
+        responder: oneshot::Sender<Response>,
+    ) {
+        self.keyword_queries.insert(
+            qid,
+            KeywordCollector {
+                keyword,
+                file_info_hashes: Vec::new(),
+            },
+        );
+        self.add_query(Query::Kad(qid), responder);
+    }
+
+    pub(crate) fn is_keyword_query(&self, qid: &QueryId) -> bool {
+        self.keyword_queries.contains_key(qid)
+    }
+
+    /// Adds the files of a keyword record found by a step of the query, skipping the ones we
+    /// already know about.
+    pub(crate) fn collect_keyword(
+        &mut self,
+        qid: &QueryId,
+        file_info_hashes: impl IntoIterator<Item = FileInfoHash>,
+    ) {
+        let Some(collector) = self.keyword_queries.get_mut(qid) else {
+            return;
+        };
+        for file_info_hash in file_info_hashes {
+            if !collector.file_info_hashes.contains(&file_info_hash) {
+                collector.file_info_hashes.push(file_info_hash);
+            }
+        }
+    }
+
+    /// Returns the keyword the query was for and the files found for it.
+    pub(crate) fn take_keyword(&mut self, qid: &QueryId) -> Option<(String, Vec<FileInfoHash>)> {
+        self.keyword_queries
+            .remove(qid)
+            .map(|collector| (collector.keyword, collector.file_info_hashes))
+    }
+
+    pub(crate) fn add_file_info_query(
+        &mut self,
+        qid: QueryId,
+        responder: oneshot::Sender<Response>,
+    ) {
+        self.file_info_queries.insert(qid);
+        self.add_query(Query::Kad(qid), responder);
+    }
+
+    pub(crate) fn is_file_info_query(&self, qid: &QueryId) -> bool {
+        self.file_info_queries.contains(qid)
+    }
+
+    /// Answers a file info query, unless it was already answered. Returns whether it answered.
+    pub(crate) fn respond_file_info(&mut self, qid: QueryId, response: Response) -> bool {
+        if !self.file_info_queries.remove(&qid) {
+            return false;
+        }
+        self.respond(Query::Kad(qid), response);
+        true
+    }
+
+    pub(crate) fn add_provider_stream(
+        &mut self,
+        qid: QueryId,
+        sender: mpsc::UnboundedSender<Vec<PeerId>>,
+    ) {
+        self.provider_streams.insert(qid, sender);
+    }
+
+    pub(crate) fn is_provider_stream(&self, qid: &QueryId) -> bool {
+        self.provider_streams.contains_key(qid)
+    }
+
+    /// Returns `false` if nobody is listening to the stream anymore.
+    pub(crate) fn stream_providers(&mut self, qid: &QueryId, providers: Vec<PeerId>) -> bool {
+        if let Some(sender) = self.provider_streams.get(qid) {
+            if sender.send(providers).is_ok() {
+                return true;
+            }
+            self.provider_streams.remove(qid);
+        }
+        false
+    }
+
+    pub(crate) fn end_provider_stream(&mut self, qid: &QueryId) {
+        self.provider_streams.remove(qid);
+    }
 }
 
 pub(crate) mod request;
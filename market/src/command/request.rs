@@ -1,23 +1,60 @@
-use libp2p::{kad::QueryId, request_response::OutboundRequestId, PeerId};
+use std::time::Duration;
+
+use libp2p::{
+    kad::QueryId, request_response::OutboundRequestId, swarm::ConnectionId, Multiaddr, PeerId,
+};
 use proto::market::{FileInfo, FileInfoHash, User};
+use tokio::sync::mpsc;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub(crate) enum Query {
     Kad(QueryId),
     ReqRes(OutboundRequestId),
+    Dial(ConnectionId),
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Clone)]
 pub(crate) enum Request {
     Listeners,
     ConnectedPeers,
-    ConnectedTo { peer_id: PeerId },
+    ConnectedTo {
+        peer_id: PeerId,
+    },
+    /// Answers once the connection is established or the dial failed.
+    Dial {
+        address: Multiaddr,
+    },
+    AddPeerAddress {
+        peer_id: PeerId,
+        address: Multiaddr,
+    },
+    Disconnect {
+        peer_id: PeerId,
+    },
+    BlockPeer {
+        peer_id: PeerId,
+    },
+    UnblockPeer {
+        peer_id: PeerId,
+    },
+    /// Changes the price of a file we registered and announces it.
+    SetPrice {
+        file_info_hash: FileInfoHash,
+        price: i64,
+    },
+    NatStatus,
+    RoutingTable,
+    Connections,
+    ExternalAddresses,
+    Metrics,
+    PrometheusMetrics,
     Kad(KadRequest),
     LocalMarketMap(LmmRequest),
     ReqRes(ReqResRequest),
+    Shutdown,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Clone)]
 pub(crate) enum KadRequest {
     GetClosestPeers {
         key: Vec<u8>,
@@ -27,8 +64,29 @@ pub(crate) enum KadRequest {
         file_info: FileInfo,
         user: User,
     },
+    /// Answers once the query is done, or earlier if `quorum` providers were found or the
+    /// `deadline` passed.
     GetProviders {
         file_info_hash: FileInfoHash,
+        quorum: Option<usize>,
+        deadline: Option<Duration>,
+    },
+    UnregisterFile {
+        file_info_hash: FileInfoHash,
+    },
+    /// Sends the providers found by every step of the query through `sender`. The sender is
+    /// dropped once the query finishes.
+    GetProvidersStream {
+        file_info_hash: FileInfoHash,
+        sender: mpsc::UnboundedSender<Vec<PeerId>>,
+    },
+    /// Answers with the files of every keyword record found by the query.
+    GetKeyword {
+        keyword: String,
+    },
+    /// Answers with the first file info record that hashes to `file_info_hash`.
+    GetFileInfo {
+        file_info_hash: FileInfoHash,
     },
 }
 
@@ -1,22 +1,88 @@
-use libp2p::{Multiaddr, PeerId};
-use proto::market::HoldersResponse;
+use libp2p::{autonat::NatStatus, Multiaddr, PeerId};
+use proto::market::{FileInfo, FileInfoHash, HoldersResponse};
 use thiserror::Error;
 use tokio::sync::oneshot::error::RecvError;
 
-use crate::lmm::FileResponse;
+use crate::{
+    keyword::SearchResult, lmm::FileResponse, ConnectionInfo, ExternalAddress, KBucket,
+    MetricsSnapshot,
+};
 
 pub type Response = Result<SuccessfulResponse, FailureResponse>;
 
 #[derive(Debug, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum SuccessfulResponse {
-    Listeners { listeners: Vec<Multiaddr> },
-    ConnectedPeers { peers: Vec<PeerId> },
-    ConnectedTo { connected: bool },
-    CheckHolders(HoldersResponse),
+    Listeners {
+        listeners: Vec<Multiaddr>,
+    },
+    ConnectedPeers {
+        peers: Vec<PeerId>,
+    },
+    ConnectedTo {
+        connected: bool,
+    },
+    /// `peer_id` is the peer we ended up connected to.
+    Dial {
+        peer_id: PeerId,
+    },
+    AddPeerAddress,
+    Disconnect,
+    BlockPeer,
+    UnblockPeer,
+    SetPrice,
+    /// `confidence` is how many probes in a row agreed with the current status.
+    NatStatus {
+        status: NatStatus,
+        confidence: usize,
+    },
+    /// The k-buckets that aren't empty, closest first.
+    RoutingTable {
+        buckets: Vec<KBucket>,
+    },
+    Connections {
+        connections: Vec<ConnectionInfo>,
+    },
+    ExternalAddresses {
+        addresses: Vec<ExternalAddress>,
+    },
+    Metrics {
+        metrics: MetricsSnapshot,
+    },
+    /// The metrics in the Prometheus text format.
+    PrometheusMetrics {
+        text: String,
+    },
+    CheckHolders {
+        response: HoldersResponse,
+        failures: Vec<HolderFailure>,
+    },
+    /// Best matches first.
+    Search {
+        results: Vec<SearchResult>,
+    },
     KadResponse(KadSuccessfulResponse),
     LmmResponse(LmmSuccessfulResponse),
     ReqResResponse(ReqResSuccessfulResponse),
+    Shutdown,
+}
+
+/// A provider that was found for a file but didn't end up in the list of holders.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HolderFailure {
+    pub peer_id: PeerId,
+    pub reason: HolderFailureReason,
+}
+
+#[derive(Debug, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum HolderFailureReason {
+    #[error("The provider did not answer in time")]
+    Timeout,
+    #[error("The provider does not hold the file anymore")]
+    NoFile,
+    #[error("{0}")]
+    Failed(FailureResponse),
 }
 
 #[derive(Debug, PartialEq, Eq, Error)]
@@ -32,6 +98,14 @@ pub enum FailureResponse {
     LmmError(LmmFailureResponse),
     #[error("[Request Response Error] - {0}")]
     ReqResError(ReqResFailureResponse),
+    #[error("Failed to dial: {0}")]
+    DialError(String),
+    #[error("Failed to add peer address: {0}")]
+    AddPeerAddressError(String),
+    #[error("Failed to disconnect: {0}")]
+    DisconnectError(String),
+    #[error("Failed to shut down: {0}")]
+    ShutdownError(String),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -43,9 +117,22 @@ pub enum LmmSuccessfulResponse {
 #[derive(Debug, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum KadSuccessfulResponse {
-    GetClosestPeers { peers: Vec<PeerId> },
+    GetClosestPeers {
+        peers: Vec<PeerId>,
+    },
     RegisterFile,
-    GetProviders { providers: Vec<PeerId> },
+    GetProviders {
+        providers: Vec<PeerId>,
+    },
+    UnregisterFile,
+    GetProvidersStream,
+    GetKeyword {
+        file_info_hashes: Vec<FileInfoHash>,
+    },
+    /// `None` if nobody stored the file info.
+    GetFileInfo {
+        file_info: Option<FileInfo>,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq, Error)]
@@ -57,11 +144,18 @@ pub enum KadFailureResponse {
     RegisterFile { error: String },
     #[error("Failed to get providers: {error}")]
     GetProviders { error: String },
+    #[error("Failed to get the files with keyword {keyword}: {error}")]
+    GetKeyword { keyword: String, error: String },
+    #[error("Failed to get the file info: {error}")]
+    GetFileInfo { error: String },
 }
 
 #[derive(Debug, PartialEq, Eq, Error)]
 #[non_exhaustive]
-pub enum LmmFailureResponse {}
+pub enum LmmFailureResponse {
+    #[error("File {file_info_hash} is not registered")]
+    NotRegistered { file_info_hash: FileInfoHash },
+}
 
 #[derive(Debug, PartialEq, Eq)]
 #[non_exhaustive]
@@ -74,4 +168,7 @@ pub enum ReqResSuccessfulResponse {
 pub enum ReqResFailureResponse {
     #[error("Failed to get holder by peer id: {error}")]
     GetHolderByPeerId { error: String },
+    /// The holder's supplier info wasn't signed by the holder or not for the file we asked about.
+    #[error("Invalid supplier info: {error}")]
+    InvalidSupplierInfo { error: String },
 }
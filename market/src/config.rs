@@ -1,17 +1,36 @@
-use std::{fmt::Debug, time::Duration};
-
-use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    fs::{self, OpenOptions},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use libp2p::{
+    connection_limits,
+    identity::{ed25519, Keypair},
+    kad::Mode,
+    multiaddr::Protocol,
+    relay, Multiaddr, PeerId,
+};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::lmm::FILE_DEFAULT_TTL;
+use crate::{lmm::FILE_DEFAULT_TTL, store::write_atomically_with};
 
 const DEFAULT_COORDINATOR_THREAD_NAME: &str = "coordinator";
 const DEFAULT_PEER_TCP_PORT: u16 = 16899;
 const DEFAULT_BOOTSTRAP_TIME: Duration = Duration::from_secs(77);
+const DEFAULT_HOLDER_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_HOLDER_CONCURRENCY: usize = 16;
+const DEFAULT_MAX_ESTABLISHED_PER_PEER: u32 = 4;
+const DEFAULT_MAX_PENDING_INCOMING: u32 = 64;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub(crate) peer_tcp_port: u16,
+    pub(crate) peer_quic_port: Option<u16>,
     pub(crate) boot_nodes: Option<BootNodes>,
     pub(crate) coordinator_thread_name: String,
     pub(crate) file_ttl: Duration,
@@ -27,6 +46,21 @@ pub struct Config {
     // public address.
     pub(crate) public_address: Option<Multiaddr>,
     pub(crate) bootstrap_time: Duration,
+    pub(crate) keypair_source: KeypairSource,
+    pub(crate) record_store: RecordStoreKind,
+    pub(crate) local_market_map_path: Option<PathBuf>,
+    pub(crate) holder_timeout: Duration,
+    pub(crate) holder_concurrency: usize,
+    pub(crate) kad_mode: Option<KadMode>,
+    pub(crate) relay_server: bool,
+    pub(crate) relay_client: bool,
+    pub(crate) dcutr: bool,
+    pub(crate) relay_limits: RelayLimits,
+    pub(crate) transport: TransportKind,
+    pub(crate) worker_threads: Option<usize>,
+    pub(crate) allowed_peers: Option<HashSet<PeerId>>,
+    pub(crate) blocked_peers: HashSet<PeerId>,
+    pub(crate) connection_limits: ConnectionLimits,
 }
 
 impl Config {
@@ -40,6 +74,11 @@ impl Config {
         self.peer_tcp_port
     }
 
+    #[inline(always)]
+    pub const fn peer_quic_port(&self) -> Option<u16> {
+        self.peer_quic_port
+    }
+
     #[inline(always)]
     pub const fn boot_nodes(&self) -> Option<&BootNodes> {
         self.boot_nodes.as_ref()
@@ -64,17 +103,111 @@ impl Config {
     pub const fn bootstrap_time(&self) -> Duration {
         self.bootstrap_time
     }
+
+    #[inline(always)]
+    pub const fn keypair_source(&self) -> &KeypairSource {
+        &self.keypair_source
+    }
+
+    #[inline(always)]
+    pub const fn record_store(&self) -> &RecordStoreKind {
+        &self.record_store
+    }
+
+    #[inline(always)]
+    pub fn local_market_map_path(&self) -> Option<&Path> {
+        self.local_market_map_path.as_deref()
+    }
+
+    #[inline(always)]
+    pub const fn holder_timeout(&self) -> Duration {
+        self.holder_timeout
+    }
+
+    #[inline(always)]
+    pub const fn holder_concurrency(&self) -> usize {
+        self.holder_concurrency
+    }
+
+    /// `None` if the mode is picked automatically.
+    #[inline(always)]
+    pub const fn kad_mode(&self) -> Option<KadMode> {
+        self.kad_mode
+    }
+
+    #[inline(always)]
+    pub const fn relay_server(&self) -> bool {
+        self.relay_server
+    }
+
+    #[inline(always)]
+    pub const fn relay_client(&self) -> bool {
+        self.relay_client
+    }
+
+    #[inline(always)]
+    pub const fn dcutr(&self) -> bool {
+        self.dcutr
+    }
+
+    #[inline(always)]
+    pub const fn relay_limits(&self) -> &RelayLimits {
+        &self.relay_limits
+    }
+
+    #[inline(always)]
+    pub const fn transport(&self) -> TransportKind {
+        self.transport
+    }
+
+    /// `None` if the runtime of [`crate::bridge::spawn`] uses one worker thread per core.
+    #[inline(always)]
+    pub const fn worker_threads(&self) -> Option<usize> {
+        self.worker_threads
+    }
+
+    /// `None` if every peer that isn't blocked may connect.
+    #[inline(always)]
+    pub const fn allowed_peers(&self) -> Option<&HashSet<PeerId>> {
+        self.allowed_peers.as_ref()
+    }
+
+    #[inline(always)]
+    pub const fn blocked_peers(&self) -> &HashSet<PeerId> {
+        &self.blocked_peers
+    }
+
+    #[inline(always)]
+    pub const fn connection_limits(&self) -> &ConnectionLimits {
+        &self.connection_limits
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             peer_tcp_port: DEFAULT_PEER_TCP_PORT,
+            peer_quic_port: None,
             boot_nodes: None,
             coordinator_thread_name: DEFAULT_COORDINATOR_THREAD_NAME.to_owned(),
             file_ttl: FILE_DEFAULT_TTL,
             public_address: None,
             bootstrap_time: DEFAULT_BOOTSTRAP_TIME,
+            keypair_source: KeypairSource::default(),
+            record_store: RecordStoreKind::default(),
+            local_market_map_path: None,
+            holder_timeout: DEFAULT_HOLDER_TIMEOUT,
+            holder_concurrency: DEFAULT_HOLDER_CONCURRENCY,
+            kad_mode: None,
+            relay_server: true,
+            relay_client: true,
+            dcutr: true,
+            relay_limits: RelayLimits::default(),
+            transport: TransportKind::default(),
+            worker_threads: None,
+            allowed_peers: None,
+            blocked_peers: HashSet::new(),
+            connection_limits: ConnectionLimits::default(),
         }
     }
 }
@@ -82,11 +215,27 @@ impl Default for Config {
 #[derive(Debug, Clone, Default)]
 pub struct ConfigBuilder {
     peer_tcp_port: Option<u16>,
+    peer_quic_port: Option<u16>,
     boot_nodes: Option<BootNodes>,
     coordinator_thread_name: Option<String>,
     file_ttl: Option<Duration>,
     public_address: Option<Multiaddr>,
     bootstrap_time: Option<Duration>,
+    keypair_source: Option<KeypairSource>,
+    record_store: Option<RecordStoreKind>,
+    local_market_map_path: Option<PathBuf>,
+    holder_timeout: Option<Duration>,
+    holder_concurrency: Option<usize>,
+    kad_mode: Option<KadMode>,
+    relay_server: Option<bool>,
+    relay_client: Option<bool>,
+    dcutr: Option<bool>,
+    relay_limits: Option<RelayLimits>,
+    transport: Option<TransportKind>,
+    worker_threads: Option<usize>,
+    allowed_peers: Option<HashSet<PeerId>>,
+    blocked_peers: Option<HashSet<PeerId>>,
+    connection_limits: Option<ConnectionLimits>,
 }
 
 impl ConfigBuilder {
@@ -96,6 +245,14 @@ impl ConfigBuilder {
         self
     }
 
+    /// Also listens for QUIC connections on this UDP port. Without it the peer can still dial
+    /// other peers over QUIC, but only accepts connections over TCP.
+    #[inline(always)]
+    pub const fn set_peer_quic_port(mut self, port: u16) -> Self {
+        self.peer_quic_port = Some(port);
+        self
+    }
+
     #[inline(always)]
     pub fn set_boot_nodes(mut self, boot_nodes: BootNodes) -> Self {
         self.boot_nodes = Some(boot_nodes);
@@ -126,10 +283,132 @@ impl ConfigBuilder {
         self
     }
 
+    /// Loads the peer's identity from `path`. If the file does not exist yet, a new keypair is
+    /// generated and saved there so that the node keeps the same [`PeerId`] across restarts.
+    #[inline(always)]
+    pub fn set_keypair_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.keypair_source = Some(KeypairSource::File(path.into()));
+        self
+    }
+
+    #[inline(always)]
+    pub fn set_keypair(mut self, keypair: Keypair) -> Self {
+        self.keypair_source = Some(KeypairSource::Provided(keypair));
+        self
+    }
+
+    /// Keeps the Kademlia records this node holds for the network in a file at `path`, so they are
+    /// still around after a restart.
+    #[inline(always)]
+    pub fn set_record_store_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record_store = Some(RecordStoreKind::Disk(path.into()));
+        self
+    }
+
+    /// Saves the files this node registered to `path`. On startup the files that have not expired
+    /// yet are loaded back and provided again without calling `register_file`.
+    #[inline(always)]
+    pub fn set_local_market_map_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.local_market_map_path = Some(path.into());
+        self
+    }
+
+    /// How long `check_holders` waits for each provider to answer.
+    #[inline(always)]
+    pub const fn set_holder_timeout(mut self, timeout: Duration) -> Self {
+        self.holder_timeout = Some(timeout);
+        self
+    }
+
+    /// How many providers `check_holders` asks at the same time. Zero is treated as one.
+    #[inline(always)]
+    pub const fn set_holder_concurrency(mut self, concurrency: usize) -> Self {
+        self.holder_concurrency = Some(concurrency);
+        self
+    }
+
+    /// Forces Kademlia into client or server mode. By default the peer only switches to server
+    /// mode once it has a confirmed external address, either from AutoNAT or from
+    /// `set_public_address`, so that unreachable peers don't advertise themselves as servers.
+    #[inline(always)]
+    pub const fn set_kad_mode(mut self, mode: KadMode) -> Self {
+        self.kad_mode = Some(mode);
+        self
+    }
+
+    /// Whether this peer relays connections for peers that can't be reached directly. Enabled by
+    /// default.
+    #[inline(always)]
+    pub const fn set_relay_server(mut self, enabled: bool) -> Self {
+        self.relay_server = Some(enabled);
+        self
+    }
+
+    /// Whether this peer can be reached through relays. Enabled by default.
+    #[inline(always)]
+    pub const fn set_relay_client(mut self, enabled: bool) -> Self {
+        self.relay_client = Some(enabled);
+        self
+    }
+
+    /// Whether this peer tries to upgrade relayed connections to direct ones. Enabled by default.
+    #[inline(always)]
+    pub const fn set_dcutr(mut self, enabled: bool) -> Self {
+        self.dcutr = Some(enabled);
+        self
+    }
+
+    /// The limits for the relay server. These are ignored if the relay server is disabled.
+    #[inline(always)]
+    pub const fn set_relay_limits(mut self, limits: RelayLimits) -> Self {
+        self.relay_limits = Some(limits);
+        self
+    }
+
+    /// Uses libp2p's in-process memory transport instead of TCP and QUIC, listening on
+    /// `/memory/<port>`. Only peers in the same process can be reached, so this is meant for tests.
+    #[inline(always)]
+    pub const fn set_memory_transport(mut self, port: u64) -> Self {
+        self.transport = Some(TransportKind::Memory(port));
+        self
+    }
+
+    /// How many worker threads the runtime that [`crate::bridge::spawn`] creates for the
+    /// coordinator has. Defaults to one per core. Zero is treated as one. This is ignored by
+    /// [`crate::bridge::spawn_in_current_runtime`].
+    #[inline(always)]
+    pub const fn set_worker_threads(mut self, worker_threads: usize) -> Self {
+        self.worker_threads = Some(worker_threads);
+        self
+    }
+
+    /// Only lets these peers connect to us and be dialed. Every peer that isn't blocked is allowed
+    /// by default. The boot nodes have to be in here as well.
+    #[inline(always)]
+    pub fn set_allowed_peers(mut self, peers: impl IntoIterator<Item = PeerId>) -> Self {
+        self.allowed_peers = Some(peers.into_iter().collect());
+        self
+    }
+
+    /// The peers that are blocked from the start. More can be blocked later with
+    /// [`crate::Peer::block_peer`].
+    #[inline(always)]
+    pub fn set_blocked_peers(mut self, peers: impl IntoIterator<Item = PeerId>) -> Self {
+        self.blocked_peers = Some(peers.into_iter().collect());
+        self
+    }
+
+    #[inline(always)]
+    pub const fn set_connection_limits(mut self, limits: ConnectionLimits) -> Self {
+        self.connection_limits = Some(limits);
+        self
+    }
+
     #[inline(always)]
     pub fn build(self) -> Config {
         Config {
             peer_tcp_port: self.peer_tcp_port.unwrap_or(DEFAULT_PEER_TCP_PORT),
+            peer_quic_port: self.peer_quic_port,
             boot_nodes: self.boot_nodes,
             coordinator_thread_name: self
                 .coordinator_thread_name
@@ -137,10 +416,210 @@ impl ConfigBuilder {
             file_ttl: self.file_ttl.unwrap_or(FILE_DEFAULT_TTL),
             public_address: self.public_address,
             bootstrap_time: self.bootstrap_time.unwrap_or(DEFAULT_BOOTSTRAP_TIME),
+            keypair_source: self.keypair_source.unwrap_or_default(),
+            record_store: self.record_store.unwrap_or_default(),
+            local_market_map_path: self.local_market_map_path,
+            holder_timeout: self.holder_timeout.unwrap_or(DEFAULT_HOLDER_TIMEOUT),
+            holder_concurrency: self
+                .holder_concurrency
+                .unwrap_or(DEFAULT_HOLDER_CONCURRENCY)
+                .max(1),
+            kad_mode: self.kad_mode,
+            relay_server: self.relay_server.unwrap_or(true),
+            relay_client: self.relay_client.unwrap_or(true),
+            dcutr: self.dcutr.unwrap_or(true),
+            relay_limits: self.relay_limits.unwrap_or_default(),
+            transport: self.transport.unwrap_or_default(),
+            worker_threads: self
+                .worker_threads
+                .map(|worker_threads| worker_threads.max(1)),
+            allowed_peers: self.allowed_peers,
+            blocked_peers: self.blocked_peers.unwrap_or_default(),
+            connection_limits: self.connection_limits.unwrap_or_default(),
+        }
+    }
+}
+
+/// Where the peer's libp2p identity comes from.
+#[derive(Debug, Clone, Default)]
+pub enum KeypairSource {
+    /// A fresh keypair is generated every time the peer is spawned.
+    #[default]
+    Ephemeral,
+    /// The keypair is read from the file, or generated and written to it on first run.
+    File(PathBuf),
+    /// A keypair supplied directly by the caller.
+    Provided(Keypair),
+}
+
+impl KeypairSource {
+    pub(crate) fn load(&self) -> Result<Keypair, KeypairError> {
+        match self {
+            KeypairSource::Ephemeral => Ok(Keypair::from(ed25519::Keypair::generate())),
+            KeypairSource::File(path) => match fs::read(path) {
+                Ok(bytes) => Keypair::from_protobuf_encoding(&bytes)
+                    .map_err(|err| KeypairError::Decode(err.to_string())),
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    let keypair = Keypair::from(ed25519::Keypair::generate());
+                    save_keypair(path, &keypair)?;
+                    Ok(keypair)
+                }
+                Err(err) => Err(KeypairError::Io(err.to_string())),
+            },
+            KeypairSource::Provided(keypair) => Ok(keypair.clone()),
+        }
+    }
+}
+
+fn save_keypair(path: &Path, keypair: &Keypair) -> Result<(), KeypairError> {
+    let bytes = keypair
+        .to_protobuf_encoding()
+        .map_err(|err| KeypairError::Decode(err.to_string()))?;
+    #[allow(unused_mut)]
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        // NOTE: this is the private key, so only the owner should be able to read it
+        options.mode(0o600);
+    }
+    // NOTE: a key file cut off by a crash could never be decoded again, so write it atomically
+    write_atomically_with(path, &bytes, options).map_err(|err| KeypairError::Io(err.to_string()))
+}
+
+/// Where the Kademlia records and provider records are kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RecordStoreKind {
+    /// The records only live in memory and are lost when the peer stops.
+    #[default]
+    Memory,
+    /// The records are also written to the file and reloaded on startup. Records that expired in
+    /// the meantime are dropped.
+    Disk(PathBuf),
+}
+
+/// How much this peer is willing to relay for other peers. The defaults are the ones of libp2p.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayLimits {
+    pub max_reservations: usize,
+    pub max_reservations_per_peer: usize,
+    pub reservation_duration: Duration,
+    pub max_circuits: usize,
+    pub max_circuits_per_peer: usize,
+    pub max_circuit_duration: Duration,
+    pub max_circuit_bytes: u64,
+}
+
+impl RelayLimits {
+    pub(crate) fn into_relay_config(self) -> relay::Config {
+        relay::Config {
+            max_reservations: self.max_reservations,
+            max_reservations_per_peer: self.max_reservations_per_peer,
+            reservation_duration: self.reservation_duration,
+            max_circuits: self.max_circuits,
+            max_circuits_per_peer: self.max_circuits_per_peer,
+            max_circuit_duration: self.max_circuit_duration,
+            max_circuit_bytes: self.max_circuit_bytes,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for RelayLimits {
+    fn default() -> Self {
+        let config = relay::Config::default();
+        Self {
+            max_reservations: config.max_reservations,
+            max_reservations_per_peer: config.max_reservations_per_peer,
+            reservation_duration: config.reservation_duration,
+            max_circuits: config.max_circuits,
+            max_circuits_per_peer: config.max_circuits_per_peer,
+            max_circuit_duration: config.max_circuit_duration,
+            max_circuit_bytes: config.max_circuit_bytes,
+        }
+    }
+}
+
+/// How many connections the peer accepts and opens. `None` means there is no limit. By default a
+/// peer can have at most 4 connections with us and at most 64 incoming connections can be pending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    pub max_pending_incoming: Option<u32>,
+    pub max_pending_outgoing: Option<u32>,
+    pub max_established_incoming: Option<u32>,
+    pub max_established_outgoing: Option<u32>,
+    pub max_established_per_peer: Option<u32>,
+    pub max_established_total: Option<u32>,
+}
+
+impl ConnectionLimits {
+    pub(crate) fn into_connection_limits(self) -> connection_limits::ConnectionLimits {
+        connection_limits::ConnectionLimits::default()
+            .with_max_pending_incoming(self.max_pending_incoming)
+            .with_max_pending_outgoing(self.max_pending_outgoing)
+            .with_max_established_incoming(self.max_established_incoming)
+            .with_max_established_outgoing(self.max_established_outgoing)
+            .with_max_established_per_peer(self.max_established_per_peer)
+            .with_max_established(self.max_established_total)
+    }
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            max_pending_incoming: Some(DEFAULT_MAX_PENDING_INCOMING),
+            max_pending_outgoing: None,
+            max_established_incoming: None,
+            max_established_outgoing: None,
+            max_established_per_peer: Some(DEFAULT_MAX_ESTABLISHED_PER_PEER),
+            max_established_total: None,
+        }
+    }
+}
+
+/// How the peer connects to other peers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransportKind {
+    /// TCP on the peer TCP port, and QUIC as well if a QUIC port is set.
+    #[default]
+    Tcp,
+    /// The in-process memory transport listening on the port.
+    Memory(u64),
+}
+
+/// Whether the peer answers the Kademlia requests of other peers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KadMode {
+    Client,
+    Server,
+}
+
+impl From<KadMode> for Mode {
+    fn from(mode: KadMode) -> Self {
+        match mode {
+            KadMode::Client => Mode::Client,
+            KadMode::Server => Mode::Server,
         }
     }
 }
 
+impl From<Mode> for KadMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Client => KadMode::Client,
+            Mode::Server => KadMode::Server,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum KeypairError {
+    #[error("Failed to read or write the keypair file: {0}")]
+    Io(String),
+    #[error("Failed to decode the keypair: {0}")]
+    Decode(String),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BootNodes {
     pub(crate) inner: Vec<Multiaddr>,
@@ -154,27 +633,18 @@ impl BootNodes {
             .expect("to fail if user does not provide required things for try_with_nodes")
     }
 
+    /// The peer id of every boot node together with its address without the `/p2p` part.
     pub fn get_kad_addrs(&self) -> impl Iterator<Item = (PeerId, Multiaddr)> + '_ {
-        // TODO: should optimize this to return lazily?
         self.inner.iter().filter_map(|addr| {
-            let peer_id = addr
-                .iter()
-                .find(|proto| matches!(proto, Protocol::P2p(_)))
-                .expect("to not fail unless try_with_nodes didn't catch this");
-            let ip4 = addr
+            let peer_id = addr.iter().find_map(|proto| match proto {
+                Protocol::P2p(peer_id) => Some(peer_id),
+                _ => None,
+            })?;
+            let addr = addr
                 .iter()
-                .find(|proto| matches!(proto, Protocol::Ip4(_)))
-                .expect("to not fail unless try_with_nodes didn't catch this");
-            let tcp = addr
-                .iter()
-                .find(|proto| matches!(proto, Protocol::Tcp(_)))
-                .expect("to not fail unless try_with_nodes didn't catch this");
-            let ip = Multiaddr::from(ip4).with(tcp);
-            if let Protocol::P2p(peer_id) = peer_id {
-                Some((peer_id, ip))
-            } else {
-                None
-            }
+                .filter(|proto| !matches!(proto, Protocol::P2p(_)))
+                .collect();
+            Some((peer_id, addr))
         })
     }
 
@@ -188,19 +658,34 @@ impl BootNodes {
                     let node: Multiaddr = node;
                     let supports_p2p = node.iter().any(|proto| matches!(proto, Protocol::P2p(_)));
 
-                    let supports_ip4 = node.iter().any(|proto| matches!(proto, Protocol::Ip4(_)));
-                    let supports_tcp = node.iter().any(|proto| matches!(proto, Protocol::Tcp(_)));
+                    let supports_host = node.iter().any(|proto| {
+                        matches!(
+                            proto,
+                            Protocol::Ip4(_)
+                                | Protocol::Ip6(_)
+                                | Protocol::Dns(_)
+                                | Protocol::Dns4(_)
+                                | Protocol::Dns6(_)
+                                | Protocol::Memory(_)
+                        )
+                    });
+                    let supports_transport = node.iter().any(|proto| {
+                        matches!(
+                            proto,
+                            Protocol::Tcp(_) | Protocol::QuicV1 | Protocol::Memory(_)
+                        )
+                    });
                     if !supports_p2p {
                         Err(BootNodesError::MissingRequiredProtocol(
                             RequiredProtocol::P2p,
                         ))
-                    } else if !supports_ip4 {
+                    } else if !supports_host {
                         Err(BootNodesError::MissingRequiredProtocol(
-                            RequiredProtocol::Ip4,
+                            RequiredProtocol::Host,
                         ))
-                    } else if !supports_tcp {
+                    } else if !supports_transport {
                         Err(BootNodesError::MissingRequiredProtocol(
-                            RequiredProtocol::Tcp,
+                            RequiredProtocol::Transport,
                         ))
                     } else {
                         Ok(node)
@@ -259,8 +744,10 @@ pub enum BootNodesError<TNode: TryInto<Multiaddr>> {
 
 #[derive(Debug)]
 pub enum RequiredProtocol {
-    Tcp,
-    Ip4,
+    /// `/tcp` or `/quic-v1`
+    Transport,
+    /// `/ip4`, `/ip6`, `/dns`, `/dns4` or `/dns6`
+    Host,
     P2p,
 }
 
@@ -270,6 +757,73 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_relay_behaviours_are_enabled_by_default() {
+        let config = Config::builder().build();
+        assert!(config.relay_server());
+        assert!(config.relay_client());
+        assert!(config.dcutr());
+        assert_eq!(config.relay_limits(), &RelayLimits::default());
+    }
+
+    #[test]
+    fn test_worker_threads() {
+        assert_eq!(Config::builder().build().worker_threads(), None);
+        let config = Config::builder().set_worker_threads(0).build();
+        assert_eq!(config.worker_threads(), Some(1));
+    }
+
+    #[test]
+    fn test_peer_lists_and_connection_limits() {
+        let config = Config::builder().build();
+        assert_eq!(config.allowed_peers(), None);
+        assert!(config.blocked_peers().is_empty());
+        assert_eq!(
+            config.connection_limits().max_established_per_peer,
+            Some(DEFAULT_MAX_ESTABLISHED_PER_PEER)
+        );
+
+        let peer_id = PeerId::random();
+        let limits = ConnectionLimits {
+            max_established_total: Some(10),
+            ..Default::default()
+        };
+        let config = Config::builder()
+            .set_allowed_peers([peer_id])
+            .set_blocked_peers([peer_id, peer_id])
+            .set_connection_limits(limits)
+            .build();
+        assert_eq!(config.allowed_peers(), Some(&HashSet::from([peer_id])));
+        assert_eq!(config.blocked_peers(), &HashSet::from([peer_id]));
+        assert_eq!(config.connection_limits(), &limits);
+    }
+
+    #[test]
+    fn test_relay_limits_are_passed_to_the_relay_server() {
+        let limits = RelayLimits {
+            max_reservations: 4,
+            max_circuits: 2,
+            max_circuit_bytes: 1024,
+            ..Default::default()
+        };
+        let config = Config::builder()
+            .set_relay_server(false)
+            .set_dcutr(false)
+            .set_relay_limits(limits)
+            .build();
+        assert!(!config.relay_server());
+        assert!(config.relay_client());
+        assert!(!config.dcutr());
+        let relay_config = config.relay_limits().into_relay_config();
+        assert_eq!(relay_config.max_reservations, 4);
+        assert_eq!(relay_config.max_circuits, 2);
+        assert_eq!(relay_config.max_circuit_bytes, 1024);
+        assert_eq!(
+            relay_config.max_circuit_duration,
+            relay::Config::default().max_circuit_duration
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_with_boot_nodes_is_empty() {
@@ -301,6 +855,93 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_boot_nodes_no_host() {
+        let res = BootNodes::try_with_nodes(vec![
+            "/tcp/4040/p2p/12D3KooWEpLeeMwsMtd6F91z4DEVjt395TvEx3Dv2i833StaFGdQ",
+        ]);
+        assert!(matches!(
+            res,
+            Err(BootNodesError::MissingRequiredProtocol(
+                RequiredProtocol::Host
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_boot_nodes_ip6_and_dns() {
+        let nodes = BootNodes::try_with_nodes(vec![
+            "/ip6/::1/tcp/4040/p2p/12D3KooWEpLeeMwsMtd6F91z4DEVjt395TvEx3Dv2i833StaFGdQ",
+            "/dns/example.com/tcp/4040/p2p/12D3KooWEpLeeMwsMtd6F91z4DEVjt395TvEx3Dv2i833StaFGdQ",
+            "/dns6/example.com/udp/4040/quic-v1/p2p/12D3KooWEpLeeMwsMtd6F91z4DEVjt395TvEx3Dv2i833StaFGdQ",
+        ])
+        .unwrap();
+        let addrs: Vec<String> = nodes
+            .get_kad_addrs()
+            .map(|(_, addr)| addr.to_string())
+            .collect();
+        assert_eq!(
+            addrs,
+            vec![
+                "/ip6/::1/tcp/4040",
+                "/dns/example.com/tcp/4040",
+                "/dns6/example.com/udp/4040/quic-v1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_boot_nodes_memory() {
+        let nodes = BootNodes::try_with_nodes(vec![
+            "/memory/42/p2p/12D3KooWEpLeeMwsMtd6F91z4DEVjt395TvEx3Dv2i833StaFGdQ",
+        ])
+        .unwrap();
+        let addrs: Vec<String> = nodes
+            .get_kad_addrs()
+            .map(|(_, addr)| addr.to_string())
+            .collect();
+        assert_eq!(addrs, vec!["/memory/42"]);
+    }
+
+    #[test]
+    fn test_keypair_file_is_created_and_reused() {
+        let path = std::env::temp_dir()
+            .join(format!("orcanet-market-{}", std::process::id()))
+            .join("identity.key");
+        let _ = fs::remove_file(&path);
+        let source = KeypairSource::File(path.clone());
+        let first = source.load().unwrap();
+        assert!(path.exists());
+        let second = source.load().unwrap();
+        assert_eq!(first.public().to_peer_id(), second.public().to_peer_id());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keypair_file_is_only_readable_by_the_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path =
+            std::env::temp_dir().join(format!("orcanet-market-private-{}.key", std::process::id()));
+        let _ = fs::remove_file(&path);
+        KeypairSource::File(path.clone()).load().unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!path.with_extension("tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_keypair_file_invalid_contents() {
+        let path =
+            std::env::temp_dir().join(format!("orcanet-market-invalid-{}.key", std::process::id()));
+        fs::write(&path, b"not a keypair").unwrap();
+        let res = KeypairSource::File(path.clone()).load();
+        assert!(matches!(res, Err(KeypairError::Decode(_))));
+        fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn test_boot_nodes() {
         let res = BootNodes::try_with_nodes(vec![
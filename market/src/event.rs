@@ -0,0 +1,74 @@
+use libp2p::{autonat::NatStatus, core::ConnectedPoint, Multiaddr, PeerId};
+use proto::market::FileInfoHash;
+
+use crate::KadMode;
+
+/// How many events a subscriber can fall behind before it starts missing them.
+pub(crate) const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// What happened on the network, sent to every receiver from [`crate::Peer::subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MarketEvent {
+    ConnectionEstablished {
+        peer_id: PeerId,
+        endpoint: ConnectedPoint,
+        num_established: u32,
+    },
+    ConnectionClosed {
+        peer_id: PeerId,
+        endpoint: ConnectedPoint,
+        num_established: u32,
+    },
+    NewListenAddr {
+        address: Multiaddr,
+    },
+    ExpiredListenAddr {
+        address: Multiaddr,
+    },
+    ExternalAddrConfirmed {
+        address: Multiaddr,
+    },
+    ExternalAddrExpired {
+        address: Multiaddr,
+    },
+    NatStatusChanged {
+        old: NatStatus,
+        new: NatStatus,
+    },
+    /// An AutoNAT server dialed us back at `address`.
+    NatProbeSucceeded {
+        server: PeerId,
+        address: Multiaddr,
+    },
+    /// An AutoNAT probe failed. `server` is `None` if there was nobody to ask.
+    NatProbeFailed {
+        server: Option<PeerId>,
+        error: String,
+    },
+    KadModeChanged {
+        mode: KadMode,
+    },
+    /// A peer was added to or updated in the Kademlia routing table.
+    RoutingUpdated {
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+        is_new_peer: bool,
+    },
+    /// A relay accepted or renewed our reservation, so we can be reached through it.
+    RelayReservationAccepted {
+        relay_peer_id: PeerId,
+        renewal: bool,
+    },
+    /// A peer asked us whether we hold a file.
+    HolderRequested {
+        peer_id: PeerId,
+        file_info_hash: FileInfoHash,
+        has_file: bool,
+    },
+    /// A peer answered whether it holds a file we asked for.
+    HolderAnswered {
+        peer_id: PeerId,
+        has_file: bool,
+    },
+}
@@ -0,0 +1,64 @@
+use libp2p::kad::RecordKey;
+use proto::market::{FileInfo, FileInfoHash};
+use thiserror::Error;
+
+/// Why a Kademlia record doesn't hold the [`FileInfo`] its key promises.
+#[derive(Debug, Error)]
+pub(crate) enum FileInfoRecordError {
+    #[error("the record is not a file info: {0}")]
+    Invalid(#[from] serde_json::Error),
+    #[error("the file info hashes to {actual} instead of the key of the record")]
+    HashMismatch { actual: FileInfoHash },
+}
+
+/// The file info record of a file is stored under the same key as its providers.
+pub(crate) fn record_key(file_info_hash: FileInfoHash) -> RecordKey {
+    file_info_hash.into_bytes().into()
+}
+
+pub(crate) fn encode(file_info: &FileInfo) -> Vec<u8> {
+    serde_json::to_vec(file_info).expect("the file info to serialize")
+}
+
+/// Decodes the file info of a record and checks that it hashes to the key of the record, so
+/// nobody can store different metadata for a file.
+pub(crate) fn decode(key: &RecordKey, value: &[u8]) -> Result<FileInfo, FileInfoRecordError> {
+    let file_info: FileInfo = serde_json::from_slice(value)?;
+    let actual = file_info.get_hash();
+    if actual.as_str().as_bytes() != key.as_ref() {
+        return Err(FileInfoRecordError::HashMismatch { actual });
+    }
+    Ok(file_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_info() -> FileInfo {
+        FileInfo {
+            file_hash: "foo".to_string(),
+            chunk_hashes: vec!["1".into(), "2".into()],
+            file_size: 8000,
+            file_name: "a_file".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_file_info_record_must_match_its_key() {
+        let file_info = file_info();
+        let key = record_key(file_info.get_hash());
+        assert_eq!(decode(&key, &encode(&file_info)).unwrap(), file_info);
+
+        let mut tampered = file_info.clone();
+        tampered.file_size = 1;
+        assert!(matches!(
+            decode(&key, &encode(&tampered)),
+            Err(FileInfoRecordError::HashMismatch { .. })
+        ));
+        assert!(matches!(
+            decode(&key, b"not a file info"),
+            Err(FileInfoRecordError::Invalid(_))
+        ));
+    }
+}
@@ -1,16 +1,42 @@
-use libp2p::autonat::Event;
+use libp2p::{
+    autonat::{Event, InboundProbeEvent, NatStatus, OutboundProbeEvent},
+    Multiaddr, Swarm,
+};
+use log::{error, info, warn};
+use tokio::sync::broadcast;
 
-use crate::BootNodes;
+use crate::{behaviour::Behaviour, BootNodes, MarketEvent};
 
 use super::EventHandler;
 
 pub(crate) struct AutoNatHandler<'a> {
+    swarm: &'a mut Swarm<Behaviour>,
     boot_nodes: Option<&'a BootNodes>,
+    events: &'a broadcast::Sender<MarketEvent>,
 }
 
 impl<'a> AutoNatHandler<'a> {
-    pub(crate) const fn new(boot_nodes: Option<&'a BootNodes>) -> Self {
-        AutoNatHandler { boot_nodes }
+    pub(crate) fn new(
+        swarm: &'a mut Swarm<Behaviour>,
+        boot_nodes: Option<&'a BootNodes>,
+        events: &'a broadcast::Sender<MarketEvent>,
+    ) -> Self {
+        AutoNatHandler {
+            swarm,
+            boot_nodes,
+            events,
+        }
+    }
+
+    // NOTE: AutoNat confirms the addresses of successful probes itself, but it never expires them
+    fn expire_external_address(&mut self, address: Multiaddr) {
+        if self.swarm.external_addresses().any(|addr| addr == &address) {
+            warn!("[AutoNat] - External address {address} is no longer reachable");
+            self.swarm.remove_external_address(&address);
+            let _ = self
+                .events
+                .send(MarketEvent::ExternalAddrExpired { address });
+        }
     }
 }
 
@@ -19,9 +45,78 @@ impl<'a> EventHandler for AutoNatHandler<'a> {
 
     fn handle_event(&mut self, event: Self::Event) {
         match event {
-            Event::InboundProbe(_) => todo!(),
-            Event::OutboundProbe(_) => todo!(),
-            Event::StatusChanged { old, new } => todo!(),
+            Event::InboundProbe(event) => match event {
+                InboundProbeEvent::Request {
+                    probe_id,
+                    peer,
+                    addresses,
+                } => {
+                    info!("[AutoNat {probe_id:?}] - {peer} asked us to dial back {addresses:?}");
+                }
+                InboundProbeEvent::Response {
+                    probe_id,
+                    peer,
+                    address,
+                } => {
+                    info!("[AutoNat {probe_id:?}] - Dialed back {peer} at {address}");
+                }
+                InboundProbeEvent::Error {
+                    probe_id,
+                    peer,
+                    error,
+                } => {
+                    warn!("[AutoNat {probe_id:?}] - Failed to dial back {peer}: {error:?}");
+                }
+            },
+            Event::OutboundProbe(event) => match event {
+                OutboundProbeEvent::Request { probe_id, peer } => {
+                    info!("[AutoNat {probe_id:?}] - Asked {peer} to dial us back");
+                }
+                OutboundProbeEvent::Response {
+                    probe_id,
+                    peer,
+                    address,
+                } => {
+                    info!("[AutoNat {probe_id:?}] - {peer} dialed us back at {address}");
+                    let _ = self.events.send(MarketEvent::NatProbeSucceeded {
+                        server: peer,
+                        address,
+                    });
+                }
+                OutboundProbeEvent::Error {
+                    probe_id,
+                    peer,
+                    error,
+                } => {
+                    if let Some(peer) = peer {
+                        warn!("[AutoNat {probe_id:?}] - {peer} failed to dial us back: {error:?}");
+                    } else {
+                        warn!("[AutoNat {probe_id:?}] - Probe aborted: {error:?}");
+                    }
+                    if self.boot_nodes.is_none() {
+                        warn!("[AutoNat {probe_id:?}] - There are no boot nodes to use as AutoNat servers");
+                    }
+                    let _ = self.events.send(MarketEvent::NatProbeFailed {
+                        server: peer,
+                        error: format!("{error:?}"),
+                    });
+                }
+            },
+            Event::StatusChanged { old, new } => {
+                info!("[AutoNat] - NAT status changed from {old:?} to {new:?}");
+                match (&old, &new) {
+                    (NatStatus::Public(old_address), NatStatus::Public(new_address))
+                        if old_address == new_address => {}
+                    (NatStatus::Public(old_address), _) => {
+                        self.expire_external_address(old_address.clone());
+                    }
+                    _ => {}
+                }
+                if new == NatStatus::Private {
+                    error!("[AutoNat] - We are not publicly reachable");
+                }
+                let _ = self.events.send(MarketEvent::NatStatusChanged { old, new });
+            }
         }
     }
 }
@@ -1,4 +1,5 @@
 use libp2p::dcutr::Event;
+use log::{info, warn};
 
 use super::EventHandler;
 
@@ -14,9 +15,13 @@ impl EventHandler for DcutrHandler {
         }: Self::Event,
     ) {
         match result {
-            Ok(conn_id) => todo!(),
+            Ok(conn_id) => {
+                info!("[DCUtR] - Upgraded the relayed connection to {remote_peer_id} to a direct connection {conn_id}");
+            }
             Err(err) => {
-                todo!();
+                warn!(
+                    "[DCUtR] - Failed to upgrade the relayed connection to {remote_peer_id}: {err}"
+                );
             }
         }
     }
@@ -0,0 +1,87 @@
+use libp2p::{gossipsub::Event, Swarm};
+use log::{error, info, warn};
+use proto::market::FileInfoHash;
+use tokio::sync::broadcast;
+
+use crate::{announcement::Announcement, behaviour::Behaviour, lmm::SupplierInfo};
+
+use super::EventHandler;
+
+/// Publishes that we offer the file at the price of the supplier info. Nobody might be
+/// subscribed yet, so failing to publish is only logged. `register_file` already refused the files
+/// that are too large to announce.
+pub(crate) fn announce(
+    swarm: &mut Swarm<Behaviour>,
+    file_info_hash: FileInfoHash,
+    supplier_info: &SupplierInfo,
+) {
+    let data = match Announcement::encode(supplier_info) {
+        Ok(data) => data,
+        Err(err) => {
+            error!("[Gossipsub] - Can't announce file {file_info_hash}: {err}");
+            return;
+        }
+    };
+    match swarm
+        .behaviour_mut()
+        .gossipsub
+        .publish(Announcement::topic(), data)
+    {
+        Ok(message_id) => {
+            info!("[Gossipsub {message_id}] - Announced file {file_info_hash}");
+        }
+        Err(err) => {
+            warn!("[Gossipsub] - Failed to announce file {file_info_hash}: {err}");
+        }
+    }
+}
+
+pub(crate) struct GossipsubHandler<'a> {
+    announcements: &'a broadcast::Sender<Announcement>,
+}
+
+impl<'a> GossipsubHandler<'a> {
+    pub(crate) const fn new(announcements: &'a broadcast::Sender<Announcement>) -> Self {
+        GossipsubHandler { announcements }
+    }
+}
+
+impl<'a> EventHandler for GossipsubHandler<'a> {
+    type Event = Event;
+
+    fn handle_event(&mut self, event: Self::Event) {
+        match event {
+            Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            } => {
+                // NOTE: messages are signed, so the source is always there
+                let peer_id = message.source.unwrap_or(propagation_source);
+                match Announcement::decode(peer_id, &message.data) {
+                    Ok(announcement) => {
+                        info!(
+                            "[Gossipsub {message_id}] - {peer_id} announced file {}",
+                            announcement.file_info_hash
+                        );
+                        let _ = self.announcements.send(announcement);
+                    }
+                    Err(err) => {
+                        error!(
+                            "[Gossipsub {message_id}] - Invalid announcement from {peer_id}: {err}"
+                        );
+                    }
+                }
+            }
+            Event::Subscribed { peer_id, topic } => {
+                info!("[Gossipsub] - {peer_id} subscribed to {topic}");
+            }
+            Event::Unsubscribed { peer_id, topic } => {
+                info!("[Gossipsub] - {peer_id} unsubscribed from {topic}");
+            }
+            Event::GossipsubNotSupported { peer_id } => {
+                warn!("[Gossipsub] - {peer_id} does not support gossipsub");
+            }
+        }
+    }
+}
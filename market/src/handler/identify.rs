@@ -1,17 +1,34 @@
-use libp2p::{identify::Event, Swarm};
+use std::collections::HashSet;
+
+use libp2p::{identify::Event, relay::HOP_PROTOCOL_NAME, PeerId, Swarm};
 use log::{error, info, warn};
 
-use crate::{behaviour::Behaviour, bridge::KAD_PROTOCOL_NAME};
+use crate::{
+    behaviour::Behaviour,
+    bridge::KAD_PROTOCOL_NAME,
+    relays::{is_global, RelayManager},
+};
 
 use super::EventHandler;
 
 pub(crate) struct IdentifyHandler<'a> {
     swarm: &'a mut Swarm<Behaviour>,
+    relays: &'a mut RelayManager,
+    /// Events of a blocked peer can still come in while its connections are being closed.
+    blocked_peers: &'a HashSet<PeerId>,
 }
 
 impl<'a> IdentifyHandler<'a> {
-    pub(crate) fn new(swarm: &'a mut Swarm<Behaviour>) -> Self {
-        IdentifyHandler { swarm }
+    pub(crate) fn new(
+        swarm: &'a mut Swarm<Behaviour>,
+        relays: &'a mut RelayManager,
+        blocked_peers: &'a HashSet<PeerId>,
+    ) -> Self {
+        IdentifyHandler {
+            swarm,
+            relays,
+            blocked_peers,
+        }
     }
 }
 
@@ -19,7 +36,26 @@ impl EventHandler for IdentifyHandler<'_> {
     type Event = Event;
     fn handle_event(&mut self, event: Self::Event) {
         match event {
+            Event::Received { peer_id, .. } if self.blocked_peers.contains(&peer_id) => {
+                warn!("[Identify] - Ignoring identify information of blocked peer {peer_id}");
+            }
             Event::Received { peer_id, info } => {
+                if info.protocols.contains(&HOP_PROTOCOL_NAME) {
+                    // NOTE: the relayed address has to be reachable for everybody, so without a
+                    // global listen address we keep the one of the boot node configuration
+                    match info.listen_addrs.iter().find(|addr| is_global(addr)) {
+                        Some(addr) => {
+                            info!("[Identify] - {peer_id} can be used as a relay through {addr}");
+                            self.relays.add_candidate(peer_id, addr.clone());
+                        }
+                        None if self.relays.has_candidate(&peer_id) => {}
+                        None => {
+                            info!("[Identify] - {peer_id} can relay, but has no global address");
+                        }
+                    }
+                } else {
+                    self.relays.remove_candidate(&peer_id);
+                }
                 if info.protocols.contains(&KAD_PROTOCOL_NAME) {
                     info!(
                         "[Identify] - {peer_id} supports Kademlia. Adding addresses {:?}",
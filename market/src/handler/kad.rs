@@ -1,30 +1,108 @@
 use libp2p::{
     kad::{
         AddProviderError, AddProviderOk, BootstrapError, Event, GetClosestPeersError,
-        GetProvidersError, GetProvidersOk, InboundRequest, ProgressStep, QueryId, QueryResult,
+        GetProvidersError, GetProvidersOk, GetRecordError, GetRecordOk, InboundRequest, Mode,
+        ProgressStep, PutRecordOk, QueryId, QueryResult, Quorum, Record,
     },
     Swarm,
 };
 use log::{error, info, warn};
-use tokio::sync::oneshot;
+use proto::market::{FileInfo, FileInfoHash};
+use tokio::sync::{broadcast, oneshot};
 
 use crate::{
+    announcement::Announcement,
     behaviour::Behaviour,
     command::{
         request::{KadRequest, Query},
         QueryHandler,
     },
-    handler::send_err,
+    file_info,
+    handler::{gossipsub::announce, send_err, send_ok},
+    keyword,
     lmm::{LocalMarketMap, SupplierInfo},
-    FailureResponse, KadFailureResponse, KadSuccessfulResponse, Response, SuccessfulResponse,
+    metrics::Metrics,
+    relays::is_relayed,
+    FailureResponse, KadFailureResponse, KadMode, KadSuccessfulResponse, MarketEvent, Response,
+    SuccessfulResponse,
 };
 
 use super::{CommandRequestHandler, EventHandler};
 
+/// Stores the file info record of the file and adds the file to the keyword records of every
+/// keyword in its name. Nobody waits for these queries, so failures are only logged.
+pub(crate) fn publish_file(
+    swarm: &mut Swarm<Behaviour>,
+    file_info_hash: &FileInfoHash,
+    file_info: &FileInfo,
+) {
+    let record = Record::new(
+        file_info::record_key(file_info_hash.clone()),
+        file_info::encode(file_info),
+    );
+    if let Err(err) = swarm.behaviour_mut().kad.put_record(record, Quorum::One) {
+        error!("[Kademlia] - Failed to store the file info record of {file_info_hash}: {err}");
+    }
+    let value = keyword::encode(std::slice::from_ref(file_info_hash));
+    for keyword in keyword::keywords(&file_info.file_name) {
+        let record = Record::new(keyword::record_key(&keyword), value.clone());
+        if let Err(err) = swarm.behaviour_mut().kad.put_record(record, Quorum::One) {
+            error!("[Kademlia] - Failed to store the keyword record for {keyword}: {err}");
+        }
+    }
+}
+
+/// Switches Kademlia to server mode while we have a confirmed external address, unless the config
+/// forces a mode. Kademlia's own automatic mode would also count relayed addresses, which turns
+/// peers behind a NAT into servers nobody can dial directly.
+#[derive(Debug)]
+pub(crate) struct KadModeManager {
+    forced: bool,
+    mode: Mode,
+}
+
+impl KadModeManager {
+    pub(crate) fn new(kad_mode: Option<KadMode>) -> Self {
+        Self {
+            forced: kad_mode.is_some(),
+            mode: kad_mode.map_or(Mode::Client, Into::into),
+        }
+    }
+
+    /// The mode Kademlia starts in.
+    pub(crate) const fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Has to be called whenever our external addresses change.
+    pub(crate) fn update(
+        &mut self,
+        swarm: &mut Swarm<Behaviour>,
+        events: &broadcast::Sender<MarketEvent>,
+    ) {
+        if self.forced {
+            return;
+        }
+        let mode = if swarm.external_addresses().any(|addr| !is_relayed(addr)) {
+            Mode::Server
+        } else {
+            Mode::Client
+        };
+        if mode != self.mode {
+            warn!("[Kademlia] - Mode changed to {mode}");
+            self.mode = mode;
+            swarm.behaviour_mut().kad.set_mode(Some(mode));
+            let _ = events.send(MarketEvent::KadModeChanged { mode: mode.into() });
+        }
+    }
+}
+
 pub(crate) struct KadHandler<'a> {
     swarm: &'a mut Swarm<Behaviour>,
     lmm: &'a mut LocalMarketMap,
     query_handler: &'a mut QueryHandler,
+    events: &'a broadcast::Sender<MarketEvent>,
+    metrics: &'a mut Metrics,
 }
 
 impl<'a> KadHandler<'a> {
@@ -32,11 +110,15 @@ impl<'a> KadHandler<'a> {
         swarm: &'a mut Swarm<Behaviour>,
         lmm: &'a mut LocalMarketMap,
         query_handler: &'a mut QueryHandler,
+        events: &'a broadcast::Sender<MarketEvent>,
+        metrics: &'a mut Metrics,
     ) -> Self {
         KadHandler {
             swarm,
             lmm,
             query_handler,
+            events,
+            metrics,
         }
     }
 
@@ -102,43 +184,197 @@ impl<'a> KadHandler<'a> {
                     }
                 }
             }
-            QueryResult::GetProviders(result) => match result {
-                Ok(maybe_ok) => {
-                    match maybe_ok {
-                        GetProvidersOk::FoundProviders { providers, .. } => {
-                            info!("[Kademlia] - GetProviders query successful");
+            QueryResult::GetProviders(result) if self.query_handler.is_provider_stream(&qid) => {
+                match result {
+                    Ok(GetProvidersOk::FoundProviders { providers, .. }) => {
+                        info!(
+                            "[Kademlia] - GetProviders query step found {} providers",
+                            providers.len()
+                        );
+                        if !self
+                            .query_handler
+                            .stream_providers(&qid, providers.into_iter().collect())
+                        {
+                            warn!("[Kademlia] - Nobody is listening for the providers anymore. Finishing the query.");
+                            if let Some(mut query) = self.swarm.behaviour_mut().kad.query_mut(&qid)
+                            {
+                                query.finish();
+                            }
+                        }
+                    }
+                    Ok(GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => {
+                        info!("[Kademlia] - GetProviders query finished");
+                    }
+                    Err(GetProvidersError::Timeout { .. }) => {
+                        error!("[Kademlia] - GetProviders query failed due to timeout.");
+                    }
+                }
+                if step.last {
+                    self.query_handler.end_provider_stream(&qid);
+                }
+            }
+            QueryResult::GetProviders(result) => {
+                let mut failed = false;
+                match result {
+                    Ok(GetProvidersOk::FoundProviders { providers, .. }) => {
+                        info!(
+                            "[Kademlia] - GetProviders query step found {} providers",
+                            providers.len()
+                        );
+                        if self.query_handler.collect_providers(&qid, providers) {
+                            info!("[Kademlia] - GetProviders query reached its quorum");
+                            if let Some(mut query) = self.swarm.behaviour_mut().kad.query_mut(&qid)
+                            {
+                                query.finish();
+                            }
+                        }
+                    }
+                    Ok(GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => {
+                        info!("[Kademlia] - GetProviders query finished");
+                    }
+                    Err(GetProvidersError::Timeout { .. }) => {
+                        error!("[Kademlia] - GetProviders query failed due to timeout.");
+                        failed = true;
+                    }
+                };
+                if step.last {
+                    let providers = self.query_handler.take_providers(&qid);
+                    // NOTE: a timeout is only a failure if nothing was found before it
+                    if failed && providers.is_empty() {
+                        self.query_handler.respond(
+                            Query::Kad(qid),
+                            Err(FailureResponse::KadError(
+                                KadFailureResponse::GetProviders {
+                                    error: "timeout".to_owned(),
+                                },
+                            )),
+                        );
+                    } else {
+                        info!("[Kademlia] - GetProviders query successful");
+                        self.query_handler.respond(
+                            Query::Kad(qid),
+                            Ok(SuccessfulResponse::KadResponse(
+                                KadSuccessfulResponse::GetProviders { providers },
+                            )),
+                        );
+                    }
+                }
+            }
+            QueryResult::GetRecord(result) if self.query_handler.is_keyword_query(&qid) => {
+                let mut failed = false;
+                match result {
+                    Ok(GetRecordOk::FoundRecord(peer_record)) => {
+                        match keyword::decode(&peer_record.record.value) {
+                            Ok(file_info_hashes) => {
+                                info!(
+                                    "[Kademlia] - GetRecord query step found {} files",
+                                    file_info_hashes.len()
+                                );
+                                self.query_handler.collect_keyword(&qid, file_info_hashes);
+                            }
+                            Err(err) => {
+                                warn!(
+                                    "[Kademlia] - Invalid keyword record from {:?}: {err}",
+                                    peer_record.peer
+                                );
+                            }
+                        }
+                    }
+                    Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => {
+                        info!("[Kademlia] - GetRecord query finished");
+                    }
+                    Err(GetRecordError::NotFound { .. }) => {
+                        info!("[Kademlia] - GetRecord query found no record");
+                    }
+                    Err(err) => {
+                        error!("[Kademlia] - GetRecord query failed: {err}");
+                        failed = true;
+                    }
+                }
+                if step.last {
+                    if let Some((keyword, file_info_hashes)) = self.query_handler.take_keyword(&qid)
+                    {
+                        // NOTE: like for providers, a timeout is only a failure if nothing was
+                        // found before it
+                        if failed && file_info_hashes.is_empty() {
                             self.query_handler.respond(
                                 Query::Kad(qid),
-                                Ok(SuccessfulResponse::KadResponse(
-                                    KadSuccessfulResponse::GetProviders {
-                                        providers: providers.into_iter().collect(),
-                                    },
-                                )),
+                                Err(FailureResponse::KadError(KadFailureResponse::GetKeyword {
+                                    keyword,
+                                    error: "timeout".to_owned(),
+                                })),
                             );
-                        }
-                        GetProvidersOk::FinishedWithNoAdditionalRecord { .. } => {
-                            warn!("[Kademlia] - GetProviders query didn't necessarily fail, but no additional records were found.");
+                        } else {
                             self.query_handler.respond(
                                 Query::Kad(qid),
                                 Ok(SuccessfulResponse::KadResponse(
-                                    KadSuccessfulResponse::GetProviders {
-                                        providers: Default::default(),
-                                    },
+                                    KadSuccessfulResponse::GetKeyword { file_info_hashes },
                                 )),
                             );
                         }
+                    }
+                }
+            }
+            QueryResult::GetRecord(result) if self.query_handler.is_file_info_query(&qid) => {
+                let mut failed = false;
+                match result {
+                    Ok(GetRecordOk::FoundRecord(peer_record)) => {
+                        let record = peer_record.record;
+                        match file_info::decode(&record.key, &record.value) {
+                            Ok(file_info) => {
+                                info!("[Kademlia] - GetRecord query found the file info");
+                                self.query_handler.respond_file_info(
+                                    qid,
+                                    Ok(SuccessfulResponse::KadResponse(
+                                        KadSuccessfulResponse::GetFileInfo {
+                                            file_info: Some(file_info),
+                                        },
+                                    )),
+                                );
+                                if let Some(mut query) =
+                                    self.swarm.behaviour_mut().kad.query_mut(&qid)
+                                {
+                                    query.finish();
+                                }
+                            }
+                            Err(err) => {
+                                warn!(
+                                    "[Kademlia] - Invalid file info record from {:?}: {err}",
+                                    peer_record.peer
+                                );
+                            }
+                        }
+                    }
+                    Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => {
+                        info!("[Kademlia] - GetRecord query finished");
+                    }
+                    Err(GetRecordError::NotFound { .. }) => {
+                        info!("[Kademlia] - GetRecord query found no record");
+                    }
+                    Err(err) => {
+                        error!("[Kademlia] - GetRecord query failed: {err}");
+                        failed = true;
+                    }
+                }
+                if step.last {
+                    let response = if failed {
+                        Err(FailureResponse::KadError(KadFailureResponse::GetFileInfo {
+                            error: "timeout".to_owned(),
+                        }))
+                    } else {
+                        Ok(SuccessfulResponse::KadResponse(
+                            KadSuccessfulResponse::GetFileInfo { file_info: None },
+                        ))
                     };
+                    self.query_handler.respond_file_info(qid, response);
                 }
-                Err(GetProvidersError::Timeout { .. }) => {
-                    error!("[Kademlia] - GetProviders query failed due to timeout.");
-                    self.query_handler.respond(
-                        Query::Kad(qid),
-                        Err(FailureResponse::KadError(
-                            KadFailureResponse::GetProviders {
-                                error: "timeout".to_owned(),
-                            },
-                        )),
-                    );
+            }
+            QueryResult::PutRecord(result) => match result {
+                Ok(PutRecordOk { .. }) => {
+                    info!("[Kademlia] - PutRecord query successful");
+                }
+                Err(err) => {
+                    warn!("[Kademlia] - PutRecord query failed: {err}");
                 }
             },
             QueryResult::StartProviding(result) => match result {
@@ -183,8 +419,14 @@ impl<'a> EventHandler for KadHandler<'a> {
         match event {
             Event::InboundRequest { request } => self.handle_inbound_request(request),
             Event::OutboundQueryProgressed {
-                id, result, step, ..
+                id,
+                result,
+                step,
+                stats,
             } => {
+                if step.last {
+                    self.metrics.record_kad_query(&result, stats.duration());
+                }
                 self.handle_outbound_event(id, result, step);
             }
             Event::RoutingUpdated {
@@ -203,6 +445,11 @@ impl<'a> EventHandler for KadHandler<'a> {
                 if let Some(old_peer) = old_peer {
                     warn!("[Kademlia] - Peer {old_peer} has been replaced by peer {peer}. The old peer has been evicted.");
                 }
+                let _ = self.events.send(MarketEvent::RoutingUpdated {
+                    peer_id: peer,
+                    addresses: addresses.into_vec(),
+                    is_new_peer,
+                });
             }
             Event::UnroutablePeer { peer } => {
                 warn!("[Kademlia] - Peer {peer} is unroutable. Peer {peer} has connected, but has no known listening addresses.");
@@ -216,6 +463,9 @@ impl<'a> EventHandler for KadHandler<'a> {
             }
             Event::ModeChanged { new_mode } => {
                 warn!("[Kademlia] - Mode changed to {new_mode}");
+                let _ = self.events.send(MarketEvent::KadModeChanged {
+                    mode: new_mode.into(),
+                });
             }
             _ => {}
         }
@@ -235,6 +485,17 @@ impl<'a> CommandRequestHandler for KadHandler<'a> {
                 file_info,
                 user,
             } => {
+                let supplier_info = SupplierInfo { file_info, user };
+                // NOTE: a file that can't be announced is refused instead of silently not announced
+                if let Err(err) = Announcement::encode(&supplier_info) {
+                    send_err!(
+                        responder,
+                        FailureResponse::KadError(KadFailureResponse::RegisterFile {
+                            error: err.to_string(),
+                        })
+                    );
+                    return;
+                }
                 let res = self
                     .swarm
                     .behaviour_mut()
@@ -242,8 +503,9 @@ impl<'a> CommandRequestHandler for KadHandler<'a> {
                     .start_providing(file_info_hash.clone().into_bytes().into());
                 match res {
                     Ok(qid) => {
-                        self.lmm
-                            .insert(file_info_hash, SupplierInfo { file_info, user });
+                        publish_file(self.swarm, &file_info_hash, &supplier_info.file_info);
+                        announce(self.swarm, file_info_hash.clone(), &supplier_info);
+                        self.lmm.insert(file_info_hash, supplier_info);
                         self.query_handler.add_query(Query::Kad(qid), responder);
                     }
                     Err(err) => {
@@ -256,13 +518,61 @@ impl<'a> CommandRequestHandler for KadHandler<'a> {
                     }
                 };
             }
-            KadRequest::GetProviders { file_info_hash } => {
+            KadRequest::GetProviders {
+                file_info_hash,
+                quorum,
+                deadline,
+            } => {
                 let qid = self
                     .swarm
                     .behaviour_mut()
                     .kad
                     .get_providers(file_info_hash.into_bytes().into());
-                self.query_handler.add_query(Query::Kad(qid), responder);
+                self.query_handler
+                    .add_provider_query(qid, responder, quorum, deadline);
+            }
+            KadRequest::GetProvidersStream {
+                file_info_hash,
+                sender,
+            } => {
+                let qid = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .get_providers(file_info_hash.into_bytes().into());
+                self.query_handler.add_provider_stream(qid, sender);
+                send_ok!(
+                    responder,
+                    SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetProvidersStream)
+                );
+            }
+            KadRequest::GetKeyword { keyword } => {
+                let qid = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .get_record(keyword::record_key(&keyword));
+                self.query_handler
+                    .add_keyword_query(qid, keyword, responder);
+            }
+            KadRequest::GetFileInfo { file_info_hash } => {
+                let qid = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .get_record(file_info::record_key(file_info_hash));
+                self.query_handler.add_file_info_query(qid, responder);
+            }
+            KadRequest::UnregisterFile { file_info_hash } => {
+                self.lmm.remove(&file_info_hash);
+                self.swarm
+                    .behaviour_mut()
+                    .kad
+                    .stop_providing(&file_info_hash.into_bytes().into());
+                send_ok!(
+                    responder,
+                    SuccessfulResponse::KadResponse(KadSuccessfulResponse::UnregisterFile)
+                );
             }
         }
     }
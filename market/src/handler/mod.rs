@@ -1,21 +1,35 @@
-use libp2p::{core::ConnectedPoint, swarm::SwarmEvent, Swarm};
+use std::collections::HashSet;
+
+use libp2p::{
+    core::ConnectedPoint,
+    identity::Keypair,
+    kad::RoutingUpdate,
+    swarm::{dial_opts::DialOpts, SwarmEvent},
+    PeerId, Swarm,
+};
 use log::{error, info, warn};
-use tokio::sync::oneshot;
+use tokio::sync::{broadcast, oneshot};
 
 use crate::{
+    announcement::Announcement,
     behaviour::Behaviour,
     command::{
-        request::{LmmRequest, Request},
+        request::{LmmRequest, Query, Request},
         QueryHandler,
     },
     handler::req_res::ReqResHandler,
+    introspection::{Introspection, KBucket},
     lmm::LocalMarketMap,
-    BootNodes, LmmSuccessfulResponse, Response, SuccessfulResponse,
+    metrics::Metrics,
+    relays::RelayManager,
+    BootNodes, FailureResponse, LmmFailureResponse, LmmSuccessfulResponse, MarketEvent, Response,
+    SuccessfulResponse,
 };
 
 use self::{
     autonat::AutoNatHandler,
     dcutr::DcutrHandler,
+    gossipsub::{announce, GossipsubHandler},
     identify::IdentifyHandler,
     kad::KadHandler,
     ping::PingHandler,
@@ -34,12 +48,36 @@ pub(crate) trait CommandRequestHandler {
     fn handle_command(&mut self, request: Self::Request, responder: oneshot::Sender<Response>);
 }
 
+/// The state the coordinator keeps for the handlers next to the swarm, the local market map and the
+/// pending queries.
+pub(crate) struct HandlerContext {
+    pub(crate) relays: RelayManager,
+    pub(crate) events: broadcast::Sender<MarketEvent>,
+    pub(crate) announcements: broadcast::Sender<Announcement>,
+    pub(crate) metrics: Metrics,
+    pub(crate) introspection: Introspection,
+    /// Signs the supplier info we send to other peers.
+    pub(crate) keypair: Keypair,
+    pub(crate) kad_mode: KadModeManager,
+    /// The peers blocked by the config or by [`Request::BlockPeer`]. The block list behaviour
+    /// can't be asked which peers it blocks.
+    pub(crate) blocked_peers: HashSet<PeerId>,
+}
+
 // NOTE: one lifetime should be covariant enough?
 pub(crate) struct Handler<'a> {
     swarm: &'a mut Swarm<Behaviour>,
     lmm: &'a mut LocalMarketMap,
+    relays: &'a mut RelayManager,
     query_handler: &'a mut QueryHandler,
     boot_nodes: Option<&'a BootNodes>,
+    events: &'a broadcast::Sender<MarketEvent>,
+    announcements: &'a broadcast::Sender<Announcement>,
+    metrics: &'a mut Metrics,
+    introspection: &'a mut Introspection,
+    keypair: &'a Keypair,
+    kad_mode: &'a mut KadModeManager,
+    blocked_peers: &'a mut HashSet<PeerId>,
 }
 
 impl<'a> Handler<'a> {
@@ -48,12 +86,31 @@ impl<'a> Handler<'a> {
         lmm: &'a mut LocalMarketMap,
         query_handler: &'a mut QueryHandler,
         boot_nodes: Option<&'a BootNodes>,
+        context: &'a mut HandlerContext,
     ) -> Self {
+        let HandlerContext {
+            relays,
+            events,
+            announcements,
+            metrics,
+            introspection,
+            keypair,
+            kad_mode,
+            blocked_peers,
+        } = context;
         Handler {
             swarm,
             lmm,
+            relays,
             query_handler,
             boot_nodes,
+            events,
+            announcements,
+            metrics,
+            introspection,
+            keypair,
+            kad_mode,
+            blocked_peers,
         }
     }
 }
@@ -67,23 +124,33 @@ impl<'a> EventHandler for Handler<'a> {
         match event {
             SwarmEvent::Behaviour(event) => match event {
                 BehaviourEvent::Kad(event) => {
-                    let mut kad_handler = KadHandler::new(self.swarm, self.lmm, self.query_handler);
+                    let mut kad_handler = KadHandler::new(
+                        self.swarm,
+                        self.lmm,
+                        self.query_handler,
+                        self.events,
+                        self.metrics,
+                    );
                     kad_handler.handle_event(event);
                 }
                 BehaviourEvent::Identify(event) => {
-                    let mut identify_handler = IdentifyHandler::new(self.swarm);
+                    let mut identify_handler =
+                        IdentifyHandler::new(self.swarm, self.relays, self.blocked_peers);
                     identify_handler.handle_event(event);
                 }
                 BehaviourEvent::Ping(event) => {
-                    let mut ping_handler = PingHandler {};
+                    let mut ping_handler = PingHandler::new(self.introspection);
                     ping_handler.handle_event(event);
                 }
                 BehaviourEvent::Autonat(event) => {
-                    let mut autonat_handler = AutoNatHandler::new(self.boot_nodes);
+                    let mut autonat_handler =
+                        AutoNatHandler::new(self.swarm, self.boot_nodes, self.events);
                     autonat_handler.handle_event(event);
+                    // NOTE: AutoNat expires addresses without a swarm event
+                    self.kad_mode.update(self.swarm, self.events);
                 }
                 BehaviourEvent::RelayServer(event) => {
-                    let mut relay_server_handler = RelayServerHandler {};
+                    let mut relay_server_handler = RelayServerHandler::new(self.metrics);
                     relay_server_handler.handle_event(event);
                 }
                 BehaviourEvent::Dcutr(event) => {
@@ -91,12 +158,25 @@ impl<'a> EventHandler for Handler<'a> {
                     dcutr_handler.handle_event(event);
                 }
                 BehaviourEvent::RelayClient(event) => {
-                    let mut relay_client = RelayClientHandler {};
+                    let mut relay_client = RelayClientHandler::new(self.events);
                     relay_client.handle_event(event);
                 }
+                BehaviourEvent::Gossipsub(event) => {
+                    let mut gossipsub_handler = GossipsubHandler::new(self.announcements);
+                    gossipsub_handler.handle_event(event);
+                }
+                BehaviourEvent::AllowedPeers(event)
+                | BehaviourEvent::BlockedPeers(event)
+                | BehaviourEvent::ConnectionLimits(event) => match event {},
                 BehaviourEvent::ReqRes(event) => {
-                    let mut req_res_handler =
-                        ReqResHandler::new(self.swarm, self.lmm, self.query_handler);
+                    let mut req_res_handler = ReqResHandler::new(
+                        self.swarm,
+                        self.lmm,
+                        self.query_handler,
+                        self.events,
+                        self.metrics,
+                        self.keypair,
+                    );
                     req_res_handler.handle_event(event);
                 }
             },
@@ -108,7 +188,7 @@ impl<'a> EventHandler for Handler<'a> {
                 established_in,
                 ..
             } => {
-                match endpoint {
+                match &endpoint {
                     ConnectedPoint::Dialer { address, .. } => {
                         info!("[Swarm ConnectionId {connection_id}] - Connection established by dialing {peer_id} at {address}");
                     }
@@ -121,6 +201,17 @@ impl<'a> EventHandler for Handler<'a> {
                 };
                 info!("[Swarm ConnectionId {connection_id}] - Connections Established with this peer: {num_established}");
                 info!("[Swarm ConnectionId {connection_id}] - Established in: {established_in:?}");
+                self.introspection
+                    .on_connection_established(peer_id, connection_id, &endpoint);
+                self.query_handler.respond(
+                    Query::Dial(connection_id),
+                    Ok(SuccessfulResponse::Dial { peer_id }),
+                );
+                let _ = self.events.send(MarketEvent::ConnectionEstablished {
+                    peer_id,
+                    endpoint,
+                    num_established: num_established.get(),
+                });
             }
             SwarmEvent::ConnectionClosed {
                 peer_id,
@@ -129,7 +220,7 @@ impl<'a> EventHandler for Handler<'a> {
                 num_established,
                 cause,
             } => {
-                match endpoint {
+                match &endpoint {
                     ConnectedPoint::Dialer { address, .. } => {
                         warn!("[Swarm ConnectionId {connection_id}] - Connection closed with {peer_id} at {address}. Dialing was used to initially establish the connection.");
                     }
@@ -141,6 +232,15 @@ impl<'a> EventHandler for Handler<'a> {
                     }
                 };
                 warn!("[Swarm ConnectionId {connection_id}] - Connections Established with this peer: {num_established}");
+                self.introspection.on_connection_closed(connection_id);
+                if num_established == 0 {
+                    self.metrics.hosted_relay_reservation_ended(&peer_id);
+                }
+                let _ = self.events.send(MarketEvent::ConnectionClosed {
+                    peer_id,
+                    endpoint,
+                    num_established,
+                });
                 if let Some(cause) = cause {
                     error!(
                         "[Swarm ConnectionId {connection_id}] - Connection closed due to: {cause}"
@@ -168,10 +268,18 @@ impl<'a> EventHandler for Handler<'a> {
             }
             SwarmEvent::OutgoingConnectionError {
                 connection_id,
-                peer_id: Some(peer_id),
+                peer_id,
                 error,
             } => {
-                error!("[Swarm ConnectionId {connection_id}] - Outgoing Connection Error to {peer_id}. Reason: {error:?}");
+                if let Some(peer_id) = peer_id {
+                    error!("[Swarm ConnectionId {connection_id}] - Outgoing Connection Error to {peer_id}. Reason: {error:?}");
+                } else {
+                    error!("[Swarm ConnectionId {connection_id}] - Outgoing Connection Error. Reason: {error:?}");
+                }
+                self.query_handler.respond(
+                    Query::Dial(connection_id),
+                    Err(FailureResponse::DialError(error.to_string())),
+                );
             }
             SwarmEvent::NewListenAddr {
                 address,
@@ -181,6 +289,7 @@ impl<'a> EventHandler for Handler<'a> {
                     "[Swarm ListenerId {listener_id}] - New Listen Address: {}",
                     address
                 );
+                let _ = self.events.send(MarketEvent::NewListenAddr { address });
             }
             SwarmEvent::ExpiredListenAddr {
                 address,
@@ -191,6 +300,7 @@ impl<'a> EventHandler for Handler<'a> {
                     "[Swarm ListenerId {listener_id}] - Expired Listen Address: {}",
                     address
                 );
+                let _ = self.events.send(MarketEvent::ExpiredListenAddr { address });
             }
             SwarmEvent::ListenerClosed {
                 addresses,
@@ -206,6 +316,9 @@ impl<'a> EventHandler for Handler<'a> {
                     warn!("[Swarm ListenerId {listener_id}] - Listener closed");
                 }
                 warn!("[Swarm ListenerId {listener_id}] - {addresses:?} are now expired");
+                if self.relays.on_listener_closed(listener_id) {
+                    self.relays.maintain(self.swarm);
+                }
             }
             SwarmEvent::ListenerError { listener_id, error } => {
                 error!("[Swarm ListenerId {listener_id}] - Listener reported an error: {error}")
@@ -230,18 +343,28 @@ impl<'a> EventHandler for Handler<'a> {
                     "[Swarm ExternalAddr] - New External Address Candidate: {}",
                     address
                 );
+                self.introspection.on_external_addr_candidate(address);
             }
             SwarmEvent::ExternalAddrConfirmed { address } => {
                 info!(
                     "[Swarm ExternalAddr] - External Address Confirmed: {}",
                     address
                 );
+                self.introspection.on_external_addr_confirmed(&address);
+                let _ = self
+                    .events
+                    .send(MarketEvent::ExternalAddrConfirmed { address });
+                self.kad_mode.update(self.swarm, self.events);
             }
             SwarmEvent::ExternalAddrExpired { address } => {
                 warn!(
                     "[Swarm ExternalAddr] - External Address Expired: {}",
                     address
                 );
+                let _ = self
+                    .events
+                    .send(MarketEvent::ExternalAddrExpired { address });
+                self.kad_mode.update(self.swarm, self.events);
             }
             _ => {}
         }
@@ -264,8 +387,146 @@ impl<'a> CommandRequestHandler for Handler<'a> {
                 let connected = self.swarm.is_connected(&peer_id);
                 send_ok!(responder, SuccessfulResponse::ConnectedTo { connected });
             }
+            Request::Dial { address } => {
+                let opts = DialOpts::from(address);
+                let connection_id = opts.connection_id();
+                match self.swarm.dial(opts) {
+                    Ok(()) => self
+                        .query_handler
+                        .add_query(Query::Dial(connection_id), responder),
+                    Err(err) => {
+                        send_err!(responder, FailureResponse::DialError(err.to_string()));
+                    }
+                }
+            }
+            Request::AddPeerAddress { peer_id, .. } if self.blocked_peers.contains(&peer_id) => {
+                send_err!(
+                    responder,
+                    FailureResponse::AddPeerAddressError(format!("{peer_id} is blocked"))
+                );
+            }
+            Request::AddPeerAddress { peer_id, address } => {
+                match self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .add_address(&peer_id, address.clone())
+                {
+                    RoutingUpdate::Success | RoutingUpdate::Pending => {
+                        send_ok!(responder, SuccessfulResponse::AddPeerAddress);
+                    }
+                    RoutingUpdate::Failed => {
+                        send_err!(
+                            responder,
+                            FailureResponse::AddPeerAddressError(format!(
+                                "{address} is not a valid address of {peer_id} or its k-bucket is full"
+                            ))
+                        );
+                    }
+                }
+            }
+            Request::Disconnect { peer_id } => {
+                if self.swarm.disconnect_peer_id(peer_id).is_ok() {
+                    send_ok!(responder, SuccessfulResponse::Disconnect);
+                } else {
+                    send_err!(
+                        responder,
+                        FailureResponse::DisconnectError(format!("Not connected to {peer_id}"))
+                    );
+                }
+            }
+            Request::BlockPeer { peer_id } => {
+                let behaviour = self.swarm.behaviour_mut();
+                // NOTE: this also closes every connection with the peer
+                behaviour.blocked_peers.block_peer(peer_id);
+                behaviour.kad.remove_peer(&peer_id);
+                self.blocked_peers.insert(peer_id);
+                send_ok!(responder, SuccessfulResponse::BlockPeer);
+            }
+            Request::UnblockPeer { peer_id } => {
+                self.swarm
+                    .behaviour_mut()
+                    .blocked_peers
+                    .unblock_peer(peer_id);
+                self.blocked_peers.remove(&peer_id);
+                send_ok!(responder, SuccessfulResponse::UnblockPeer);
+            }
+            Request::SetPrice {
+                file_info_hash,
+                price,
+            } => match self.lmm.set_price(&file_info_hash, price) {
+                Some(supplier_info) => {
+                    announce(self.swarm, file_info_hash, &supplier_info);
+                    send_ok!(responder, SuccessfulResponse::SetPrice);
+                }
+                None => {
+                    send_err!(
+                        responder,
+                        FailureResponse::LmmError(LmmFailureResponse::NotRegistered {
+                            file_info_hash
+                        })
+                    );
+                }
+            },
+            Request::NatStatus => {
+                let autonat = &self.swarm.behaviour().autonat;
+                send_ok!(
+                    responder,
+                    SuccessfulResponse::NatStatus {
+                        status: autonat.nat_status(),
+                        confidence: autonat.confidence(),
+                    }
+                );
+            }
+            Request::RoutingTable => {
+                let buckets = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .kbuckets()
+                    .map(KBucket::new)
+                    .collect();
+                send_ok!(responder, SuccessfulResponse::RoutingTable { buckets });
+            }
+            Request::Connections => {
+                let connections = self.introspection.connections();
+                send_ok!(responder, SuccessfulResponse::Connections { connections });
+            }
+            Request::ExternalAddresses => {
+                let addresses = self
+                    .introspection
+                    .external_addresses(self.swarm.external_addresses());
+                send_ok!(
+                    responder,
+                    SuccessfulResponse::ExternalAddresses { addresses }
+                );
+            }
+            Request::Metrics => {
+                self.metrics.refresh(self.swarm, self.lmm, self.relays);
+                send_ok!(
+                    responder,
+                    SuccessfulResponse::Metrics {
+                        metrics: self.metrics.snapshot()
+                    }
+                );
+            }
+            Request::PrometheusMetrics => {
+                self.metrics.refresh(self.swarm, self.lmm, self.relays);
+                send_ok!(
+                    responder,
+                    SuccessfulResponse::PrometheusMetrics {
+                        text: self.metrics.encode()
+                    }
+                );
+            }
             Request::Kad(kad_request) => {
-                let mut handler = KadHandler::new(self.swarm, self.lmm, self.query_handler);
+                let mut handler = KadHandler::new(
+                    self.swarm,
+                    self.lmm,
+                    self.query_handler,
+                    self.events,
+                    self.metrics,
+                );
                 handler.handle_command(kad_request, responder);
             }
             Request::LocalMarketMap(lmm_request) => {
@@ -273,9 +534,26 @@ impl<'a> CommandRequestHandler for Handler<'a> {
                 handler.handle_command(lmm_request, responder);
             }
             Request::ReqRes(req_res_request) => {
-                let mut handler = ReqResHandler::new(self.swarm, self.lmm, self.query_handler);
+                let mut handler = ReqResHandler::new(
+                    self.swarm,
+                    self.lmm,
+                    self.query_handler,
+                    self.events,
+                    self.metrics,
+                    self.keypair,
+                );
                 handler.handle_command(req_res_request, responder);
             }
+            Request::Shutdown => {
+                // NOTE: the coordinator shuts itself down before the request gets here
+                error!("[Handler] - The shutdown request reached the command handler");
+                send_err!(
+                    responder,
+                    FailureResponse::ShutdownError(
+                        "the command handler can't shut the coordinator down".to_owned()
+                    )
+                );
+            }
         };
     }
 }
@@ -331,8 +609,11 @@ mod macros {
 pub(crate) use macros::send_err;
 pub(crate) use macros::send_ok;
 
+pub(crate) use self::kad::{publish_file, KadModeManager};
+
 mod autonat;
 mod dcutr;
+mod gossipsub;
 mod identify;
 mod kad;
 mod ping;
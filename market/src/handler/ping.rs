@@ -1,12 +1,22 @@
 use libp2p::ping::{Event, Failure};
 use log::{error, info};
 
+use crate::introspection::Introspection;
+
 use super::EventHandler;
 
 #[derive(Debug)]
-pub(crate) struct PingHandler;
+pub(crate) struct PingHandler<'a> {
+    introspection: &'a mut Introspection,
+}
+
+impl<'a> PingHandler<'a> {
+    pub(crate) fn new(introspection: &'a mut Introspection) -> Self {
+        PingHandler { introspection }
+    }
+}
 
-impl EventHandler for PingHandler {
+impl<'a> EventHandler for PingHandler<'a> {
     type Event = Event;
 
     fn handle_event(
@@ -23,6 +33,7 @@ impl EventHandler for PingHandler {
                     "[ConnId: {connection}] Ping to peer {} succeeded in {:?}ms",
                     peer, ms
                 );
+                self.introspection.on_ping(connection, ms);
             }
             Err(err) => match err {
                 Failure::Timeout => {
@@ -1,10 +1,20 @@
 use libp2p::relay::client::Event;
+use log::info;
+use tokio::sync::broadcast;
 
-use crate::handler::EventHandler;
+use crate::{handler::EventHandler, MarketEvent};
 
-pub(crate) struct RelayClientHandler {}
+pub(crate) struct RelayClientHandler<'a> {
+    events: &'a broadcast::Sender<MarketEvent>,
+}
+
+impl<'a> RelayClientHandler<'a> {
+    pub(crate) const fn new(events: &'a broadcast::Sender<MarketEvent>) -> Self {
+        RelayClientHandler { events }
+    }
+}
 
-impl EventHandler for RelayClientHandler {
+impl<'a> EventHandler for RelayClientHandler<'a> {
     type Event = Event;
 
     fn handle_event(&mut self, event: Self::Event) {
@@ -13,12 +23,27 @@ impl EventHandler for RelayClientHandler {
                 relay_peer_id,
                 renewal,
                 limit,
-            } => todo!(),
+            } => {
+                if renewal {
+                    info!("[Relay Client] - Reservation with {relay_peer_id} was renewed");
+                } else {
+                    info!("[Relay Client] - Reservation with {relay_peer_id} was accepted");
+                }
+                info!("[Relay Client] - Reservation limit: {limit:?}");
+                let _ = self.events.send(MarketEvent::RelayReservationAccepted {
+                    relay_peer_id,
+                    renewal,
+                });
+            }
             Event::OutboundCircuitEstablished {
                 relay_peer_id,
                 limit,
-            } => todo!(),
-            Event::InboundCircuitEstablished { src_peer_id, limit } => todo!(),
+            } => {
+                info!("[Relay Client] - Circuit established through {relay_peer_id} with limit {limit:?}");
+            }
+            Event::InboundCircuitEstablished { src_peer_id, limit } => {
+                info!("[Relay Client] - {src_peer_id} reached us through a relay with limit {limit:?}");
+            }
         }
     }
 }
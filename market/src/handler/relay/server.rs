@@ -1,32 +1,63 @@
 use libp2p::relay::Event;
+use log::{info, warn};
 
-use crate::handler::EventHandler;
+use crate::{handler::EventHandler, metrics::Metrics};
 
-pub(crate) struct RelayServerHandler;
+pub(crate) struct RelayServerHandler<'a> {
+    metrics: &'a mut Metrics,
+}
+
+impl<'a> RelayServerHandler<'a> {
+    pub(crate) fn new(metrics: &'a mut Metrics) -> Self {
+        RelayServerHandler { metrics }
+    }
+}
 
-impl EventHandler for RelayServerHandler {
+impl<'a> EventHandler for RelayServerHandler<'a> {
     type Event = Event;
     fn handle_event(&mut self, event: Self::Event) {
         match event {
             Event::ReservationReqAccepted {
                 src_peer_id,
                 renewed,
-            } => todo!(),
-            Event::ReservationReqDenied { src_peer_id } => todo!(),
-            Event::ReservationTimedOut { src_peer_id } => todo!(),
+            } => {
+                if renewed {
+                    info!("[Relay Server] - Renewed the reservation of {src_peer_id}");
+                } else {
+                    info!("[Relay Server] - Accepted a reservation from {src_peer_id}");
+                    self.metrics.hosted_relay_reservation_accepted(src_peer_id);
+                }
+            }
+            Event::ReservationReqDenied { src_peer_id } => {
+                warn!("[Relay Server] - Denied a reservation from {src_peer_id}");
+            }
+            Event::ReservationTimedOut { src_peer_id } => {
+                info!("[Relay Server] - The reservation of {src_peer_id} timed out");
+                self.metrics.hosted_relay_reservation_ended(&src_peer_id);
+            }
             Event::CircuitReqDenied {
                 src_peer_id,
                 dst_peer_id,
-            } => todo!(),
+            } => {
+                warn!("[Relay Server] - Denied a circuit from {src_peer_id} to {dst_peer_id}");
+            }
             Event::CircuitReqAccepted {
                 src_peer_id,
                 dst_peer_id,
-            } => todo!(),
+            } => {
+                info!("[Relay Server] - Relaying a circuit from {src_peer_id} to {dst_peer_id}");
+            }
             Event::CircuitClosed {
                 src_peer_id,
                 dst_peer_id,
                 error,
-            } => todo!(),
+            } => {
+                if let Some(error) = error {
+                    warn!("[Relay Server] - Circuit from {src_peer_id} to {dst_peer_id} closed: {error}");
+                } else {
+                    info!("[Relay Server] - Circuit from {src_peer_id} to {dst_peer_id} closed");
+                }
+            }
             _ => {}
         }
     }
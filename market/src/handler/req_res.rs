@@ -1,10 +1,11 @@
 use libp2p::{
+    identity::Keypair,
     request_response::{Event, Message},
     Swarm,
 };
 use log::{error, info, warn};
 use proto::market::FileInfoHash;
-use tokio::sync::oneshot::Sender;
+use tokio::sync::{broadcast, oneshot::Sender};
 
 use crate::{
     behaviour::Behaviour,
@@ -13,8 +14,10 @@ use crate::{
         QueryHandler,
     },
     handler::send_ok,
-    lmm::{FileResponse, LocalMarketMap},
-    FailureResponse, ReqResFailureResponse, ReqResSuccessfulResponse, Response, SuccessfulResponse,
+    lmm::{FileResponse, LocalMarketMap, SignedFileResponse},
+    metrics::{Direction, Metrics},
+    FailureResponse, MarketEvent, ReqResFailureResponse, ReqResSuccessfulResponse, Response,
+    SuccessfulResponse,
 };
 
 use super::{CommandRequestHandler, EventHandler};
@@ -23,6 +26,9 @@ pub(super) struct ReqResHandler<'a> {
     swarm: &'a mut Swarm<Behaviour>,
     lmm: &'a mut LocalMarketMap,
     query_handler: &'a mut QueryHandler,
+    events: &'a broadcast::Sender<MarketEvent>,
+    metrics: &'a mut Metrics,
+    keypair: &'a Keypair,
 }
 
 impl<'a> ReqResHandler<'a> {
@@ -30,17 +36,23 @@ impl<'a> ReqResHandler<'a> {
         swarm: &'a mut Swarm<Behaviour>,
         lmm: &'a mut LocalMarketMap,
         query_handler: &'a mut QueryHandler,
+        events: &'a broadcast::Sender<MarketEvent>,
+        metrics: &'a mut Metrics,
+        keypair: &'a Keypair,
     ) -> Self {
         ReqResHandler {
             swarm,
             lmm,
             query_handler,
+            events,
+            metrics,
+            keypair,
         }
     }
 }
 
 impl<'a> EventHandler for ReqResHandler<'a> {
-    type Event = Event<FileInfoHash, FileResponse>;
+    type Event = Event<FileInfoHash, SignedFileResponse>;
 
     fn handle_event(&mut self, event: Self::Event) {
         match event {
@@ -63,6 +75,18 @@ impl<'a> EventHandler for ReqResHandler<'a> {
                             FileResponse::NoFile
                         }
                     };
+                    let _ = self.events.send(MarketEvent::HolderRequested {
+                        peer_id: peer,
+                        file_info_hash: request.clone(),
+                        has_file: matches!(response, FileResponse::HasFile(_)),
+                    });
+                    let response = match SignedFileResponse::sign(self.keypair, request, response) {
+                        Ok(response) => response,
+                        Err(err) => {
+                            error!("[RequestResponse {request_id:?}] - Failed to sign the supplier info: {err}");
+                            SignedFileResponse::NoFile
+                        }
+                    };
 
                     if self
                         .swarm
@@ -84,6 +108,32 @@ impl<'a> EventHandler for ReqResHandler<'a> {
                         "[RequestResponse {request_id:?}] - Received response from {}",
                         peer
                     );
+                    let Some(file_info_hash) = self.query_handler.take_holder_request(&request_id)
+                    else {
+                        warn!("[RequestResponse {request_id:?}] - Received a response nobody asked for");
+                        return;
+                    };
+                    let response = match response.verify(&peer, &file_info_hash) {
+                        Ok(response) => response,
+                        Err(err) => {
+                            error!("[RequestResponse {request_id:?}] - Rejected the supplier info of {peer}: {err}");
+                            self.metrics.record_req_res(Direction::Outbound, false);
+                            self.query_handler.respond(
+                                Query::ReqRes(request_id),
+                                Err(FailureResponse::ReqResError(
+                                    ReqResFailureResponse::InvalidSupplierInfo {
+                                        error: err.to_string(),
+                                    },
+                                )),
+                            );
+                            return;
+                        }
+                    };
+                    self.metrics.record_req_res(Direction::Outbound, true);
+                    let _ = self.events.send(MarketEvent::HolderAnswered {
+                        peer_id: peer,
+                        has_file: matches!(response, FileResponse::HasFile(_)),
+                    });
                     self.query_handler.respond(
                         Query::ReqRes(request_id),
                         Ok(SuccessfulResponse::ReqResResponse(
@@ -101,6 +151,8 @@ impl<'a> EventHandler for ReqResHandler<'a> {
                     "[RequestResponse {request_id:?}] - Outbound request failure to peer: {}",
                     peer
                 );
+                self.metrics.record_req_res(Direction::Outbound, false);
+                self.query_handler.take_holder_request(&request_id);
                 self.query_handler.respond(
                     Query::ReqRes(request_id),
                     Err(FailureResponse::ReqResError(
@@ -120,9 +172,11 @@ impl<'a> EventHandler for ReqResHandler<'a> {
                     peer
                 );
                 error!("[RequestResponse {request_id:?}] - Error: {}", error);
+                self.metrics.record_req_res(Direction::Inbound, false);
             }
             Event::ResponseSent { peer, request_id } => {
                 warn!("[RequestResponse {request_id:?}] - Response sent to peer: {peer}");
+                self.metrics.record_req_res(Direction::Inbound, true);
             }
         }
     }
@@ -155,12 +209,13 @@ impl<'a> CommandRequestHandler for ReqResHandler<'a> {
                         )
                     );
                 } else {
-                    let qid = self
+                    let request_id = self
                         .swarm
                         .behaviour_mut()
                         .req_res
-                        .send_request(&peer_id, file_info_hash);
-                    self.query_handler.add_query(Query::ReqRes(qid), responder);
+                        .send_request(&peer_id, file_info_hash.clone());
+                    self.query_handler
+                        .add_holder_request(request_id, file_info_hash, responder);
                 }
             }
         }
@@ -0,0 +1,235 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use libp2p::{
+    core::ConnectedPoint,
+    kad::{self, KBucketRef},
+    multiaddr::Protocol,
+    swarm::ConnectionId,
+    Multiaddr, PeerId,
+};
+
+/// How many unconfirmed external address candidates we remember.
+const MAX_EXTERNAL_ADDR_CANDIDATES: usize = 16;
+
+/// Keeps track of what the swarm doesn't let us look up later: the endpoints of the open
+/// connections and the external address candidates that weren't confirmed yet.
+#[derive(Debug, Default)]
+pub(crate) struct Introspection {
+    connections: HashMap<ConnectionId, ConnectionInfo>,
+    external_addr_candidates: VecDeque<Multiaddr>,
+}
+
+impl Introspection {
+    pub(crate) fn on_connection_established(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        endpoint: &ConnectedPoint,
+    ) {
+        let (role, remote_address) = match endpoint {
+            ConnectedPoint::Dialer { address, .. } => (ConnectionRole::Dialer, address.clone()),
+            ConnectedPoint::Listener { send_back_addr, .. } => {
+                (ConnectionRole::Listener, send_back_addr.clone())
+            }
+        };
+        let relayed = remote_address
+            .iter()
+            .any(|proto| matches!(proto, Protocol::P2pCircuit));
+        self.connections.insert(
+            connection_id,
+            ConnectionInfo {
+                peer_id,
+                connection_id,
+                role,
+                remote_address,
+                relayed,
+                rtt: None,
+            },
+        );
+    }
+
+    pub(crate) fn on_connection_closed(&mut self, connection_id: ConnectionId) {
+        self.connections.remove(&connection_id);
+    }
+
+    pub(crate) fn on_ping(&mut self, connection_id: ConnectionId, rtt: Duration) {
+        if let Some(connection) = self.connections.get_mut(&connection_id) {
+            connection.rtt = Some(rtt);
+        }
+    }
+
+    pub(crate) fn on_external_addr_candidate(&mut self, address: Multiaddr) {
+        if self.external_addr_candidates.contains(&address) {
+            return;
+        }
+        if self.external_addr_candidates.len() == MAX_EXTERNAL_ADDR_CANDIDATES {
+            self.external_addr_candidates.pop_front();
+        }
+        self.external_addr_candidates.push_back(address);
+    }
+
+    pub(crate) fn on_external_addr_confirmed(&mut self, address: &Multiaddr) {
+        self.external_addr_candidates
+            .retain(|candidate| candidate != address);
+    }
+
+    /// The open connections ordered by peer.
+    pub(crate) fn connections(&self) -> Vec<ConnectionInfo> {
+        let mut connections: Vec<_> = self.connections.values().cloned().collect();
+        connections.sort_by_key(|connection| (connection.peer_id, connection.connection_id));
+        connections
+    }
+
+    /// The confirmed external addresses followed by the candidates that weren't confirmed.
+    pub(crate) fn external_addresses<'a>(
+        &self,
+        confirmed: impl Iterator<Item = &'a Multiaddr>,
+    ) -> Vec<ExternalAddress> {
+        let mut addresses: Vec<_> = confirmed
+            .map(|address| ExternalAddress {
+                address: address.clone(),
+                confirmed: true,
+            })
+            .collect();
+        for candidate in &self.external_addr_candidates {
+            if addresses
+                .iter()
+                .all(|address| &address.address != candidate)
+            {
+                addresses.push(ExternalAddress {
+                    address: candidate.clone(),
+                    confirmed: false,
+                });
+            }
+        }
+        addresses
+    }
+}
+
+/// One of the Kademlia k-buckets that isn't empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KBucket {
+    /// The bucket holds the peers whose distance to us has this base 2 logarithm.
+    pub index: u32,
+    pub entries: Vec<RoutingTableEntry>,
+}
+
+impl KBucket {
+    pub(crate) fn new(bucket: KBucketRef<'_, kad::KBucketKey<PeerId>, kad::Addresses>) -> Self {
+        let index = bucket.range().0.ilog2().unwrap_or_default();
+        let entries = bucket
+            .iter()
+            .map(|entry| RoutingTableEntry {
+                peer_id: *entry.node.key.preimage(),
+                addresses: entry.node.value.iter().cloned().collect(),
+                connected: matches!(entry.status, kad::NodeStatus::Connected),
+            })
+            .collect();
+        Self { index, entries }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingTableEntry {
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    /// Whether Kademlia considers the peer connected.
+    pub connected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub peer_id: PeerId,
+    pub connection_id: ConnectionId,
+    pub role: ConnectionRole,
+    pub remote_address: Multiaddr,
+    /// Whether the connection goes through a relay.
+    pub relayed: bool,
+    /// The round trip time of the last successful ping on this connection.
+    pub rtt: Option<Duration>,
+}
+
+/// Whether we dialed the peer or the peer dialed us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionRole {
+    Dialer,
+    Listener,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalAddress {
+    pub address: Multiaddr,
+    /// Unconfirmed addresses are candidates that were observed by other peers but not confirmed
+    /// to be reachable yet.
+    pub confirmed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn addr(port: u16) -> Multiaddr {
+        Multiaddr::empty()
+            .with(Protocol::Ip4([127, 0, 0, 1].into()))
+            .with(Protocol::Tcp(port))
+    }
+
+    #[test]
+    fn test_relayed_listener_connection() {
+        let mut introspection = Introspection::default();
+        let peer_id = PeerId::random();
+        let relay = PeerId::random();
+        let connection_id = ConnectionId::new_unchecked(1);
+        let send_back_addr = addr(1)
+            .with(Protocol::P2p(relay))
+            .with(Protocol::P2pCircuit);
+        introspection.on_connection_established(
+            peer_id,
+            connection_id,
+            &ConnectedPoint::Listener {
+                local_addr: addr(2),
+                send_back_addr: send_back_addr.clone(),
+            },
+        );
+        introspection.on_ping(connection_id, Duration::from_millis(3));
+        assert_eq!(
+            introspection.connections(),
+            vec![ConnectionInfo {
+                peer_id,
+                connection_id,
+                role: ConnectionRole::Listener,
+                remote_address: send_back_addr,
+                relayed: true,
+                rtt: Some(Duration::from_millis(3)),
+            }]
+        );
+        introspection.on_connection_closed(connection_id);
+        assert!(introspection.connections().is_empty());
+    }
+
+    #[test]
+    fn test_external_addresses_with_candidates() {
+        let mut introspection = Introspection::default();
+        for port in 0..MAX_EXTERNAL_ADDR_CANDIDATES as u16 + 2 {
+            introspection.on_external_addr_candidate(addr(port));
+        }
+        introspection.on_external_addr_candidate(addr(5));
+        introspection.on_external_addr_confirmed(&addr(5));
+        let confirmed = [addr(5)];
+        let addresses = introspection.external_addresses(confirmed.iter());
+        assert_eq!(addresses.len(), MAX_EXTERNAL_ADDR_CANDIDATES);
+        assert_eq!(
+            addresses[0],
+            ExternalAddress {
+                address: addr(5),
+                confirmed: true
+            }
+        );
+        assert!(addresses[1..].iter().all(|address| !address.confirmed));
+        assert!(addresses.iter().all(|address| address.address != addr(0)));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use libp2p::kad::RecordKey;
+use proto::market::{FileInfo, FileInfoHash};
+
+const KEYWORD_KEY_PREFIX: &str = "/orcanet/keyword/";
+/// How many keywords of a file name are indexed. The rest of a very long name is ignored.
+pub(crate) const MAX_KEYWORDS: usize = 16;
+/// How many files a keyword record holds before the oldest entries are dropped. Kademlia drops
+/// messages larger than 16 KiB, and a file info hash takes 67 bytes of the record, so a full record
+/// still fits in a `GetRecord` response with room to spare.
+const MAX_KEYWORD_ENTRIES: usize = 128;
+
+/// A file whose name matched some of the keywords of a query, see [`crate::Peer::search`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub file_info_hash: FileInfoHash,
+    pub file_info: FileInfo,
+    /// How many keywords of the query are in the name of the file.
+    pub matching_keywords: usize,
+}
+
+/// Splits a file name or a query into lowercase alphanumeric keywords, without duplicates and in
+/// the order they first appear in.
+pub(crate) fn keywords(text: &str) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+    for keyword in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|keyword| !keyword.is_empty())
+        .map(str::to_lowercase)
+    {
+        if !keywords.contains(&keyword) {
+            keywords.push(keyword);
+        }
+        if keywords.len() == MAX_KEYWORDS {
+            break;
+        }
+    }
+    keywords
+}
+
+pub(crate) fn record_key(keyword: &str) -> RecordKey {
+    RecordKey::new(&format!("{KEYWORD_KEY_PREFIX}{keyword}"))
+}
+
+pub(crate) fn is_keyword_key(key: &RecordKey) -> bool {
+    key.as_ref().starts_with(KEYWORD_KEY_PREFIX.as_bytes())
+}
+
+pub(crate) fn encode(file_info_hashes: &[FileInfoHash]) -> Vec<u8> {
+    serde_json::to_vec(file_info_hashes).expect("the file info hashes to serialize")
+}
+
+pub(crate) fn decode(value: &[u8]) -> serde_json::Result<Vec<FileInfoHash>> {
+    serde_json::from_slice(value)
+}
+
+/// Adds the files of an incoming keyword record to the ones we already have. Every peer that
+/// registers a file with the keyword publishes its own record, so replacing the record would
+/// forget about the files of everybody else.
+pub(crate) fn merge(existing: &[u8], incoming: &[u8]) -> serde_json::Result<Vec<u8>> {
+    let incoming = decode(incoming)?;
+    // NOTE: a broken record we stored earlier is simply replaced
+    let mut file_info_hashes = decode(existing).unwrap_or_default();
+    for file_info_hash in incoming {
+        if !file_info_hashes.contains(&file_info_hash) {
+            file_info_hashes.push(file_info_hash);
+        }
+    }
+    let overflow = file_info_hashes.len().saturating_sub(MAX_KEYWORD_ENTRIES);
+    file_info_hashes.drain(..overflow);
+    Ok(encode(&file_info_hashes))
+}
+
+/// Counts in how many of the keyword lookups every file showed up, most matches first. Files with
+/// the same number of matches are ordered by their hash to keep the order stable.
+pub(crate) fn rank(
+    lookups: impl IntoIterator<Item = Vec<FileInfoHash>>,
+) -> Vec<(FileInfoHash, usize)> {
+    let mut matches: HashMap<FileInfoHash, usize> = HashMap::new();
+    for file_info_hashes in lookups {
+        for file_info_hash in file_info_hashes {
+            *matches.entry(file_info_hash).or_default() += 1;
+        }
+    }
+    let mut ranked: Vec<_> = matches.into_iter().collect();
+    ranked.sort_by(|(a_hash, a_matches), (b_hash, b_matches)| {
+        b_matches
+            .cmp(a_matches)
+            .then_with(|| a_hash.as_str().cmp(b_hash.as_str()))
+    });
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn hash(hash: &str) -> FileInfoHash {
+        FileInfoHash::new(hash.to_owned())
+    }
+
+    #[test]
+    fn test_keywords_are_normalized() {
+        assert_eq!(
+            keywords("My_Holiday-Video (2) my video.MP4"),
+            vec!["my", "holiday", "video", "2", "mp4"]
+        );
+        assert!(keywords(" .-_ ").is_empty());
+        let long_name = (0..100)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(keywords(&long_name).len(), MAX_KEYWORDS);
+    }
+
+    #[test]
+    fn test_merge_keeps_the_files_of_both_records() {
+        let existing = encode(&[hash("a"), hash("b")]);
+        let incoming = encode(&[hash("b"), hash("c")]);
+        let merged = merge(&existing, &incoming).unwrap();
+        assert_eq!(
+            decode(&merged).unwrap(),
+            vec![hash("a"), hash("b"), hash("c")]
+        );
+        assert!(merge(&existing, b"not a keyword record").is_err());
+        assert_eq!(
+            decode(&merge(b"not a keyword record", &incoming).unwrap()).unwrap(),
+            vec![hash("b"), hash("c")]
+        );
+    }
+
+    #[test]
+    fn test_merge_drops_the_oldest_files() {
+        let existing: Vec<_> = (0..MAX_KEYWORD_ENTRIES)
+            .map(|i| hash(&i.to_string()))
+            .collect();
+        let merged = merge(&encode(&existing), &encode(&[hash("new")])).unwrap();
+        let merged = decode(&merged).unwrap();
+        assert_eq!(merged.len(), MAX_KEYWORD_ENTRIES);
+        assert_eq!(merged[0], hash("1"));
+        assert_eq!(merged.last(), Some(&hash("new")));
+    }
+
+    #[test]
+    fn test_full_record_fits_in_a_kad_message() {
+        // NOTE: the limit of Kademlia minus some room for the key and the rest of the message
+        const MAX_RECORD_SIZE: usize = 12 * 1024;
+        let full: Vec<_> = (0..MAX_KEYWORD_ENTRIES)
+            .map(|i| {
+                FileInfo {
+                    file_hash: i.to_string(),
+                    chunk_hashes: vec![],
+                    file_size: 0,
+                    file_name: String::new(),
+                }
+                .get_hash()
+            })
+            .collect();
+        let merged = merge(&encode(&full), &encode(&full[..1])).unwrap();
+        assert!(merged.len() < MAX_RECORD_SIZE);
+        assert_eq!(decode(&merged).unwrap(), full);
+    }
+
+    #[test]
+    fn test_rank_by_matching_keywords() {
+        let ranked = rank([
+            vec![hash("b"), hash("c")],
+            vec![hash("a"), hash("c")],
+            vec![hash("c")],
+        ]);
+        assert_eq!(ranked, vec![(hash("c"), 3), (hash("a"), 1), (hash("b"), 1)]);
+    }
+}
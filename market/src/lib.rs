@@ -8,20 +8,38 @@
 )]
 #![deny(unsafe_code, unreachable_pub)]
 
+pub use announcement::Announcement;
 pub use bridge::peer::Peer;
 pub use command::response::*;
 pub use config::*;
+pub use event::MarketEvent;
+pub use introspection::{
+    ConnectionInfo, ConnectionRole, ExternalAddress, KBucket, RoutingTableEntry,
+};
+pub use keyword::SearchResult;
 pub use libp2p::{
+    autonat::NatStatus,
     build_multiaddr,
     multiaddr::{multiaddr, Protocol},
     Multiaddr,
 };
 pub use lmm::{FileResponse, SupplierInfo};
+pub use metrics::{KadQueryKind, KadQueryMetrics, MetricsSnapshot};
 
+pub(crate) mod announcement;
 pub(crate) mod behaviour;
 pub(crate) mod command;
+pub(crate) mod event;
+pub(crate) mod file_info;
 pub(crate) mod handler;
+pub(crate) mod introspection;
+pub(crate) mod keyword;
 pub(crate) mod lmm;
+pub(crate) mod metrics;
+pub(crate) mod relays;
+pub(crate) mod store;
 
 pub mod bridge;
 pub mod config;
+#[cfg(feature = "test-support")]
+pub mod testing;
@@ -1,17 +1,36 @@
 use std::{
     collections::HashMap,
-    time::{Duration, Instant},
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use libp2p::{
+    core::{
+        signed_envelope::{DecodingError, ReadPayloadError},
+        SignedEnvelope,
+    },
+    identity::{Keypair, SigningError},
+    PeerId,
+};
+use log::error;
 use proto::market::{FileInfo, FileInfoHash, User};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::store::write_atomically;
 
 pub(crate) const FILE_DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+pub(crate) const LMM_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+const SUPPLIER_INFO_DOMAIN: &str = "orcanet-supplier-info";
+const SUPPLIER_INFO_PAYLOAD_TYPE: &[u8] = b"/orcanet/supplier-info/1.0.0";
 
 #[derive(Debug, Clone)]
 pub(crate) struct LocalMarketMap {
     inner: HashMap<FileInfoHash, LocalMarketEntry>,
     file_ttl: Duration,
+    path: Option<PathBuf>,
 }
 
 impl LocalMarketMap {
@@ -22,29 +41,146 @@ impl LocalMarketMap {
             Self {
                 inner: HashMap::new(),
                 file_ttl,
+                path: None,
+            }
+        }
+    }
+
+    /// Creates a map that is saved to `path` whenever it changes. Entries that are already in the
+    /// file and have not expired yet are loaded back.
+    pub(crate) fn with_path(file_ttl: Duration, path: PathBuf) -> io::Result<Self> {
+        let mut lmm = Self::new(file_ttl);
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let entries: Vec<StoredEntry> = serde_json::from_slice(&bytes)
+                    .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+                let now = Instant::now();
+                let wall_now = SystemTime::now();
+                for entry in entries {
+                    let registered_at = UNIX_EPOCH + Duration::from_millis(entry.registered_at_ms);
+                    let elapsed = wall_now.duration_since(registered_at).unwrap_or_default();
+                    if elapsed >= file_ttl {
+                        continue;
+                    }
+                    let registered_at = now.checked_sub(elapsed).unwrap_or(now);
+                    lmm.inner
+                        .insert(entry.file_info_hash, (registered_at, entry.supplier_info));
+                }
             }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
         }
+        lmm.path = Some(path);
+        lmm.persist();
+        Ok(lmm)
     }
 
     pub(crate) fn insert(&mut self, file_info_hash: FileInfoHash, supplier_info: SupplierInfo) {
         self.inner
             .insert(file_info_hash, (Instant::now(), supplier_info));
+        self.persist();
+    }
+
+    pub(crate) fn remove(&mut self, file_info_hash: &FileInfoHash) -> Option<SupplierInfo> {
+        let removed = self.inner.remove(file_info_hash).map(|entry| entry.1);
+        if removed.is_some() {
+            self.persist();
+        }
+        removed
+    }
+
+    /// Removes every expired entry and returns the hashes of the files that were removed.
+    pub(crate) fn remove_expired(&mut self) -> Vec<FileInfoHash> {
+        let now = Instant::now();
+        let expired: Vec<FileInfoHash> = self
+            .inner
+            .iter()
+            .filter(|(_, (registered_at, _))| now.duration_since(*registered_at) >= self.file_ttl)
+            .map(|(file_info_hash, _)| file_info_hash.clone())
+            .collect();
+        if !expired.is_empty() {
+            for file_info_hash in &expired {
+                self.inner.remove(file_info_hash);
+            }
+            self.persist();
+        }
+        expired
     }
 
-    pub(crate) fn get_if_not_expired(
+    /// Changes the price of a file that hasn't expired yet and returns its updated supplier info.
+    pub(crate) fn set_price(
         &mut self,
         file_info_hash: &FileInfoHash,
+        price: i64,
     ) -> Option<SupplierInfo> {
-        if let Some(entry) = self.inner.get(file_info_hash) {
-            let elapsed_time = Instant::now().duration_since(entry.0);
-            if elapsed_time >= self.file_ttl {
-                self.inner.remove(file_info_hash);
-                None
-            } else {
-                Some(entry.1.clone())
-            }
-        } else {
+        self.get_if_not_expired(file_info_hash)?;
+        let (_, supplier_info) = self.inner.get_mut(file_info_hash)?;
+        supplier_info.user.price = price;
+        let supplier_info = supplier_info.clone();
+        self.persist();
+        Some(supplier_info)
+    }
+
+    /// The number of files that haven't expired yet.
+    pub(crate) fn active_len(&self) -> usize {
+        let now = Instant::now();
+        self.inner
+            .values()
+            .filter(|(registered_at, _)| now.duration_since(*registered_at) < self.file_ttl)
+            .count()
+    }
+
+    pub(crate) fn file_info_hashes(&self) -> impl Iterator<Item = &FileInfoHash> {
+        self.inner.keys()
+    }
+
+    /// Every file with its info, including the ones that expired but weren't swept yet.
+    pub(crate) fn files(&self) -> impl Iterator<Item = (&FileInfoHash, &FileInfo)> {
+        self.inner
+            .iter()
+            .map(|(file_info_hash, (_, supplier_info))| (file_info_hash, &supplier_info.file_info))
+    }
+
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let wall_now = SystemTime::now();
+        let now = Instant::now();
+        let entries: Vec<StoredEntry> = self
+            .inner
+            .iter()
+            .map(|(file_info_hash, (registered_at, supplier_info))| {
+                let registered_at = wall_now - now.duration_since(*registered_at);
+                StoredEntry {
+                    file_info_hash: file_info_hash.clone(),
+                    registered_at_ms: registered_at
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64,
+                    supplier_info: supplier_info.clone(),
+                }
+            })
+            .collect();
+        let res = serde_json::to_vec(&entries)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+            .and_then(|bytes| write_atomically(path, &bytes));
+        if let Err(err) = res {
+            error!(
+                "[Local Market Map] - Failed to save to {}: {err}",
+                path.display()
+            );
+        }
+    }
+
+    /// The supplier info of a file that hasn't expired yet. Expired entries are left for
+    /// [`LocalMarketMap::remove_expired`], so that the sweep also stops providing them.
+    pub(crate) fn get_if_not_expired(&self, file_info_hash: &FileInfoHash) -> Option<SupplierInfo> {
+        let (registered_at, supplier_info) = self.inner.get(file_info_hash)?;
+        if Instant::now().duration_since(*registered_at) >= self.file_ttl {
             None
+        } else {
+            Some(supplier_info.clone())
         }
     }
 }
@@ -57,6 +193,13 @@ impl Default for LocalMarketMap {
 
 pub(crate) type LocalMarketEntry = (Instant, SupplierInfo);
 
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredEntry {
+    file_info_hash: FileInfoHash,
+    registered_at_ms: u64,
+    supplier_info: SupplierInfo,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct SupplierInfo {
     pub file_info: FileInfo,
@@ -69,6 +212,84 @@ pub enum FileResponse {
     NoFile,
 }
 
+/// What a holder answers over request/response. The supplier info is wrapped in a signed envelope
+/// so the requester can check that the peer it asked is the one that registered it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum SignedFileResponse {
+    HasFile(Vec<u8>),
+    NoFile,
+}
+
+/// The payload of the envelope. The hash is signed as well so that the supplier info can't be
+/// passed off as the answer for another file.
+#[derive(Debug, Serialize, Deserialize)]
+struct SignedSupplierInfo {
+    file_info_hash: FileInfoHash,
+    supplier_info: SupplierInfo,
+}
+
+impl SignedFileResponse {
+    pub(crate) fn sign(
+        keypair: &Keypair,
+        file_info_hash: FileInfoHash,
+        response: FileResponse,
+    ) -> Result<Self, SigningError> {
+        let FileResponse::HasFile(supplier_info) = response else {
+            return Ok(Self::NoFile);
+        };
+        let payload = serde_json::to_vec(&SignedSupplierInfo {
+            file_info_hash,
+            supplier_info,
+        })
+        .expect("the supplier info to serialize");
+        let envelope = SignedEnvelope::new(
+            keypair,
+            SUPPLIER_INFO_DOMAIN.to_owned(),
+            SUPPLIER_INFO_PAYLOAD_TYPE.to_vec(),
+            payload,
+        )?;
+        Ok(Self::HasFile(envelope.into_protobuf_encoding()))
+    }
+
+    /// Checks that the supplier info was signed by `peer_id` for `file_info_hash`.
+    pub(crate) fn verify(
+        self,
+        peer_id: &PeerId,
+        file_info_hash: &FileInfoHash,
+    ) -> Result<FileResponse, SupplierInfoError> {
+        let Self::HasFile(envelope) = self else {
+            return Ok(FileResponse::NoFile);
+        };
+        let envelope = SignedEnvelope::from_protobuf_encoding(&envelope)?;
+        let (payload, key) = envelope
+            .payload_and_signing_key(SUPPLIER_INFO_DOMAIN.to_owned(), SUPPLIER_INFO_PAYLOAD_TYPE)?;
+        let signer = key.to_peer_id();
+        if &signer != peer_id {
+            return Err(SupplierInfoError::WrongSigner { signer });
+        }
+        let signed: SignedSupplierInfo = serde_json::from_slice(payload)
+            .map_err(|err| SupplierInfoError::Payload(err.to_string()))?;
+        if &signed.file_info_hash != file_info_hash {
+            return Err(SupplierInfoError::WrongFile);
+        }
+        Ok(FileResponse::HasFile(signed.supplier_info))
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum SupplierInfoError {
+    #[error("Failed to decode the envelope: {0}")]
+    Envelope(#[from] DecodingError),
+    #[error("{0}")]
+    Signature(#[from] ReadPayloadError),
+    #[error("The supplier info was signed by {signer}")]
+    WrongSigner { signer: PeerId },
+    #[error("Failed to decode the supplier info: {0}")]
+    Payload(String),
+    #[error("The supplier info was signed for another file")]
+    WrongFile,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,5 +339,125 @@ mod tests {
         lmm.insert(file_hash.clone(), supplier_info);
         sleep(Duration::from_millis(20));
         assert_eq!(lmm.get_if_not_expired(&file_hash), None);
+        assert_eq!(lmm.remove_expired(), vec![file_hash]);
+    }
+
+    fn supplier_info() -> SupplierInfo {
+        SupplierInfo {
+            file_info: FileInfo {
+                file_hash: "foo".to_string(),
+                chunk_hashes: vec!["1".into(), "2".into()],
+                file_size: 8000,
+                file_name: "a_file".to_string(),
+            },
+            user: User {
+                ip: Ipv4Addr::new(127, 0, 0, 1).to_string(),
+                port: 8080,
+                price: 100,
+                name: "Alice".to_string(),
+                id: "416".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_signed_file_response_is_verified_against_the_peer_and_the_file() {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+        let supplier_info = supplier_info();
+        let file_info_hash = supplier_info.file_info.get_hash();
+        let signed = SignedFileResponse::sign(
+            &keypair,
+            file_info_hash.clone(),
+            FileResponse::HasFile(supplier_info.clone()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            signed.clone().verify(&peer_id, &file_info_hash).unwrap(),
+            FileResponse::HasFile(supplier_info)
+        );
+        assert!(matches!(
+            signed.clone().verify(&PeerId::random(), &file_info_hash),
+            Err(SupplierInfoError::WrongSigner { signer }) if signer == peer_id
+        ));
+        assert!(matches!(
+            signed.verify(&peer_id, &FileInfoHash::new("other".to_owned())),
+            Err(SupplierInfoError::WrongFile)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_file_response_is_rejected() {
+        let keypair = Keypair::generate_ed25519();
+        let file_info_hash = supplier_info().file_info.get_hash();
+        let SignedFileResponse::HasFile(mut envelope) = SignedFileResponse::sign(
+            &keypair,
+            file_info_hash.clone(),
+            FileResponse::HasFile(supplier_info()),
+        )
+        .unwrap() else {
+            panic!("expected a signed supplier info");
+        };
+        // NOTE: the port is in the payload, which sits right before the signature
+        let port = envelope
+            .windows(4)
+            .position(|window| window == b"8080")
+            .unwrap();
+        envelope[port] = b'9';
+        assert!(matches!(
+            SignedFileResponse::HasFile(envelope)
+                .verify(&keypair.public().to_peer_id(), &file_info_hash),
+            Err(SupplierInfoError::Signature(
+                ReadPayloadError::InvalidSignature
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_remove_expired() {
+        let mut lmm = LocalMarketMap::new(Duration::from_millis(10));
+        let supplier_info = supplier_info();
+        let file_hash = supplier_info.file_info.get_hash();
+        lmm.insert(file_hash.clone(), supplier_info);
+        assert_eq!(lmm.active_len(), 1);
+        assert!(lmm.remove_expired().is_empty());
+        sleep(Duration::from_millis(20));
+        assert_eq!(lmm.active_len(), 0);
+        assert_eq!(lmm.remove_expired(), vec![file_hash]);
+        assert_eq!(lmm.file_info_hashes().count(), 0);
+    }
+
+    #[test]
+    fn test_entries_survive_reload() {
+        let path = std::env::temp_dir().join(format!("orcanet-lmm-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let supplier_info = supplier_info();
+        let file_hash = supplier_info.file_info.get_hash();
+        {
+            let mut lmm = LocalMarketMap::with_path(Duration::from_secs(60), path.clone()).unwrap();
+            lmm.insert(file_hash.clone(), supplier_info.clone());
+        }
+        let lmm = LocalMarketMap::with_path(Duration::from_secs(60), path.clone()).unwrap();
+        assert_eq!(lmm.get_if_not_expired(&file_hash), Some(supplier_info));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_expired_entries_are_not_reloaded() {
+        let path =
+            std::env::temp_dir().join(format!("orcanet-lmm-expired-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let supplier_info = supplier_info();
+        let file_hash = supplier_info.file_info.get_hash();
+        {
+            let mut lmm =
+                LocalMarketMap::with_path(Duration::from_millis(10), path.clone()).unwrap();
+            lmm.insert(file_hash.clone(), supplier_info);
+        }
+        sleep(Duration::from_millis(20));
+        let lmm = LocalMarketMap::with_path(Duration::from_millis(10), path.clone()).unwrap();
+        assert_eq!(lmm.file_info_hashes().count(), 0);
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,382 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Duration,
+};
+
+use libp2p::{kad::QueryResult, PeerId, Swarm};
+use prometheus_client::{
+    encoding::{text, EncodeLabelSet, EncodeLabelValue},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+
+use crate::{behaviour::Behaviour, lmm::LocalMarketMap, relays::RelayManager};
+
+const METRICS_PREFIX: &str = "orcanet_market";
+
+/// The metrics the coordinator records. The gauges that are read off the swarm are only brought up
+/// to date by [`Metrics::refresh`] right before they are read.
+pub(crate) struct Metrics {
+    registry: Registry,
+    routing_table_size: Gauge,
+    connected_peers: Gauge,
+    local_market_map_size: Gauge,
+    kad_queries: Family<KadQueryLabels, Counter>,
+    kad_query_duration: Family<KadQueryKindLabels, Histogram>,
+    req_res: Family<ReqResLabels, Counter>,
+    relay_reservations: Family<RelayLabels, Gauge>,
+    /// The peers that hold a reservation with our relay server. The relay server drops the
+    /// reservations of closed connections without an event, so we can't just count the events.
+    hosted_reservations: HashSet<PeerId>,
+    // NOTE: the histograms can't be read back, so the snapshot keeps its own totals
+    kad_query_stats: BTreeMap<KadQueryKind, KadQueryMetrics>,
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics")
+            .field("kad_query_stats", &self.kad_query_stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        let mut registry = Registry::with_prefix(METRICS_PREFIX);
+        let routing_table_size = Gauge::default();
+        registry.register(
+            "routing_table_size",
+            "Number of peers in the Kademlia routing table",
+            routing_table_size.clone(),
+        );
+        let connected_peers = Gauge::default();
+        registry.register(
+            "connected_peers",
+            "Number of peers we have at least one connection with",
+            connected_peers.clone(),
+        );
+        let local_market_map_size = Gauge::default();
+        registry.register(
+            "local_market_map_size",
+            "Number of files we registered that haven't expired yet",
+            local_market_map_size.clone(),
+        );
+        let kad_queries = Family::default();
+        registry.register(
+            "kad_queries",
+            "Number of finished Kademlia queries by type and outcome",
+            kad_queries.clone(),
+        );
+        let kad_query_duration =
+            Family::<KadQueryKindLabels, Histogram>::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.01, 2.0, 12))
+            });
+        registry.register(
+            "kad_query_duration_seconds",
+            "How long the finished Kademlia queries took by type",
+            kad_query_duration.clone(),
+        );
+        let req_res = Family::default();
+        registry.register(
+            "req_res",
+            "Number of holder requests by direction and outcome",
+            req_res.clone(),
+        );
+        let relay_reservations = Family::default();
+        registry.register(
+            "relay_reservations",
+            "Number of relay reservations we hold with relays or host for other peers",
+            relay_reservations.clone(),
+        );
+        Self {
+            registry,
+            routing_table_size,
+            connected_peers,
+            local_market_map_size,
+            kad_queries,
+            kad_query_duration,
+            req_res,
+            relay_reservations,
+            hosted_reservations: HashSet::new(),
+            kad_query_stats: BTreeMap::new(),
+        }
+    }
+
+    /// Records a query once its last step finished.
+    pub(crate) fn record_kad_query(&mut self, result: &QueryResult, duration: Option<Duration>) {
+        let (kind, succeeded) = KadQueryKind::from_result(result);
+        let outcome = if succeeded {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        };
+        self.kad_queries
+            .get_or_create(&KadQueryLabels { kind, outcome })
+            .inc();
+        let stats = self.kad_query_stats.entry(kind).or_default();
+        if succeeded {
+            stats.succeeded += 1;
+        } else {
+            stats.failed += 1;
+        }
+        if let Some(duration) = duration {
+            self.kad_query_duration
+                .get_or_create(&KadQueryKindLabels { kind })
+                .observe(duration.as_secs_f64());
+            stats.total_duration += duration;
+        }
+    }
+
+    pub(crate) fn record_req_res(&self, direction: Direction, succeeded: bool) {
+        let outcome = if succeeded {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        };
+        self.req_res
+            .get_or_create(&ReqResLabels { direction, outcome })
+            .inc();
+    }
+
+    pub(crate) fn hosted_relay_reservation_accepted(&mut self, peer_id: PeerId) {
+        self.hosted_reservations.insert(peer_id);
+        self.set_hosted_relay_reservations();
+    }
+
+    /// Has to be called when the reservation timed out and when the last connection with the
+    /// peer closed.
+    pub(crate) fn hosted_relay_reservation_ended(&mut self, peer_id: &PeerId) {
+        if self.hosted_reservations.remove(peer_id) {
+            self.set_hosted_relay_reservations();
+        }
+    }
+
+    fn set_hosted_relay_reservations(&self) {
+        self.relay_reservations
+            .get_or_create(&RelayLabels {
+                role: RelayRole::Server,
+            })
+            .set(self.hosted_reservations.len() as i64);
+    }
+
+    /// Brings the gauges that are read off the swarm and the local market map up to date.
+    pub(crate) fn refresh(
+        &self,
+        swarm: &mut Swarm<Behaviour>,
+        lmm: &LocalMarketMap,
+        relays: &RelayManager,
+    ) {
+        let routing_table_size: usize = swarm
+            .behaviour_mut()
+            .kad
+            .kbuckets()
+            .map(|bucket| bucket.num_entries())
+            .sum();
+        self.routing_table_size.set(routing_table_size as i64);
+        self.connected_peers
+            .set(swarm.connected_peers().count() as i64);
+        self.local_market_map_size.set(lmm.active_len() as i64);
+        self.relay_reservations
+            .get_or_create(&RelayLabels {
+                role: RelayRole::Client,
+            })
+            .set(relays.reservation_count() as i64);
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let req_res = |outcome| {
+            [Direction::Inbound, Direction::Outbound]
+                .into_iter()
+                .map(|direction| {
+                    self.req_res
+                        .get_or_create(&ReqResLabels { direction, outcome })
+                        .get()
+                })
+                .sum()
+        };
+        let relay_reservations = |role| {
+            self.relay_reservations
+                .get_or_create(&RelayLabels { role })
+                .get()
+                .max(0) as usize
+        };
+        MetricsSnapshot {
+            routing_table_size: self.routing_table_size.get() as usize,
+            connected_peers: self.connected_peers.get() as usize,
+            local_market_map_size: self.local_market_map_size.get() as usize,
+            kad_queries: self.kad_query_stats.clone(),
+            req_res_successes: req_res(Outcome::Success),
+            req_res_failures: req_res(Outcome::Failure),
+            relay_reservations: relay_reservations(RelayRole::Client),
+            hosted_relay_reservations: relay_reservations(RelayRole::Server),
+        }
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub(crate) fn encode(&self) -> String {
+        let mut buffer = String::new();
+        text::encode(&mut buffer, &self.registry).expect("writing to a string to succeed");
+        buffer
+    }
+}
+
+/// The metrics of a peer at the time [`crate::Peer::metrics`] was called.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub routing_table_size: usize,
+    pub connected_peers: usize,
+    /// Files we registered that haven't expired yet.
+    pub local_market_map_size: usize,
+    pub kad_queries: BTreeMap<KadQueryKind, KadQueryMetrics>,
+    /// Holder requests we answered or got an answer to.
+    pub req_res_successes: u64,
+    pub req_res_failures: u64,
+    /// Reservations we hold with relays while we are private.
+    pub relay_reservations: usize,
+    /// Reservations other peers hold with our relay server.
+    pub hosted_relay_reservations: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KadQueryMetrics {
+    pub succeeded: u64,
+    pub failed: u64,
+    /// The time all finished queries of this type took together.
+    pub total_duration: Duration,
+}
+
+impl KadQueryMetrics {
+    pub fn mean_duration(&self) -> Option<Duration> {
+        let finished = u32::try_from(self.succeeded + self.failed).ok()?;
+        self.total_duration.checked_div(finished)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EncodeLabelValue)]
+#[non_exhaustive]
+pub enum KadQueryKind {
+    Bootstrap,
+    GetClosestPeers,
+    GetProviders,
+    StartProviding,
+    RepublishProvider,
+    GetRecord,
+    PutRecord,
+    RepublishRecord,
+}
+
+impl KadQueryKind {
+    /// The type of the query and whether it succeeded.
+    const fn from_result(result: &QueryResult) -> (Self, bool) {
+        match result {
+            QueryResult::Bootstrap(result) => (Self::Bootstrap, result.is_ok()),
+            QueryResult::GetClosestPeers(result) => (Self::GetClosestPeers, result.is_ok()),
+            QueryResult::GetProviders(result) => (Self::GetProviders, result.is_ok()),
+            QueryResult::StartProviding(result) => (Self::StartProviding, result.is_ok()),
+            QueryResult::RepublishProvider(result) => (Self::RepublishProvider, result.is_ok()),
+            QueryResult::GetRecord(result) => (Self::GetRecord, result.is_ok()),
+            QueryResult::PutRecord(result) => (Self::PutRecord, result.is_ok()),
+            QueryResult::RepublishRecord(result) => (Self::RepublishRecord, result.is_ok()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue)]
+pub(crate) enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue)]
+enum Outcome {
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue)]
+enum RelayRole {
+    Client,
+    Server,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+struct KadQueryLabels {
+    kind: KadQueryKind,
+    outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+struct KadQueryKindLabels {
+    kind: KadQueryKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+struct ReqResLabels {
+    direction: Direction,
+    outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+struct RelayLabels {
+    role: RelayRole,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::kad::{BootstrapError, BootstrapOk};
+    use libp2p::PeerId;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_kad_queries_are_counted_by_kind() {
+        let mut metrics = Metrics::new();
+        let peer = PeerId::random();
+        metrics.record_kad_query(
+            &QueryResult::Bootstrap(Ok(BootstrapOk {
+                peer,
+                num_remaining: 0,
+            })),
+            Some(Duration::from_secs(1)),
+        );
+        metrics.record_kad_query(
+            &QueryResult::Bootstrap(Err(BootstrapError::Timeout {
+                peer,
+                num_remaining: None,
+            })),
+            Some(Duration::from_secs(3)),
+        );
+        let snapshot = metrics.snapshot();
+        let bootstrap = snapshot.kad_queries[&KadQueryKind::Bootstrap];
+        assert_eq!(bootstrap.succeeded, 1);
+        assert_eq!(bootstrap.failed, 1);
+        assert_eq!(bootstrap.mean_duration(), Some(Duration::from_secs(2)));
+        assert!(metrics.encode().contains(
+            "orcanet_market_kad_queries_total{kind=\"Bootstrap\",outcome=\"Success\"} 1"
+        ));
+    }
+
+    #[test]
+    fn test_req_res_and_hosted_reservations() {
+        let mut metrics = Metrics::new();
+        metrics.record_req_res(Direction::Inbound, true);
+        metrics.record_req_res(Direction::Outbound, true);
+        metrics.record_req_res(Direction::Outbound, false);
+        let [a, b] = [PeerId::random(), PeerId::random()];
+        metrics.hosted_relay_reservation_accepted(a);
+        metrics.hosted_relay_reservation_accepted(b);
+        // NOTE: a renewal on a new connection, and the connection closing after the timeout
+        metrics.hosted_relay_reservation_accepted(b);
+        metrics.hosted_relay_reservation_ended(&a);
+        metrics.hosted_relay_reservation_ended(&a);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.req_res_successes, 2);
+        assert_eq!(snapshot.req_res_failures, 1);
+        assert_eq!(snapshot.hosted_relay_reservations, 1);
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use libp2p::{
+    autonat::NatStatus, core::transport::ListenerId, multiaddr::Protocol, Multiaddr, PeerId, Swarm,
+};
+use log::{info, warn};
+
+use crate::{behaviour::Behaviour, BootNodes};
+
+pub(crate) const RELAY_MAINTENANCE_INTERVAL: Duration = Duration::from_secs(30);
+/// How many relays we keep a reservation with while we are private.
+const RELAY_RESERVATIONS: usize = 2;
+/// How long we wait before trying a relay again after its reservation failed.
+const RELAY_RETRY_BACKOFF: Duration = Duration::from_secs(60 * 5);
+
+/// Keeps relayed listeners open through a few relays while AutoNAT finds us to be private.
+///
+/// The relay client takes care of renewing the reservations and of reporting the relayed
+/// addresses as external addresses. We only pick the relays and replace the ones that fail.
+#[derive(Debug)]
+pub(crate) struct RelayManager {
+    enabled: bool,
+    candidates: HashMap<PeerId, Multiaddr>,
+    listeners: HashMap<ListenerId, PeerId>,
+    failed: HashMap<PeerId, Instant>,
+}
+
+impl RelayManager {
+    /// The boot nodes are used as relays until identify tells us they can't relay.
+    pub(crate) fn new(enabled: bool, boot_nodes: Option<&BootNodes>) -> Self {
+        let candidates = boot_nodes
+            .map(|boot_nodes| boot_nodes.get_kad_addrs().collect())
+            .unwrap_or_default();
+        Self {
+            enabled,
+            candidates,
+            listeners: HashMap::new(),
+            failed: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn add_candidate(&mut self, peer_id: PeerId, addr: Multiaddr) {
+        self.candidates.insert(peer_id, addr);
+    }
+
+    pub(crate) fn has_candidate(&self, peer_id: &PeerId) -> bool {
+        self.candidates.contains_key(peer_id)
+    }
+
+    pub(crate) fn remove_candidate(&mut self, peer_id: &PeerId) {
+        self.candidates.remove(peer_id);
+    }
+
+    /// Forgets a relayed listener that closed. Returns `false` if it wasn't one of ours.
+    pub(crate) fn on_listener_closed(&mut self, listener_id: ListenerId) -> bool {
+        let Some(relay) = self.listeners.remove(&listener_id) else {
+            return false;
+        };
+        warn!("[Relay] - Lost the reservation with {relay}");
+        self.failed.insert(relay, Instant::now());
+        true
+    }
+
+    /// Reserves circuits with new relays while we are private, and closes the relayed listeners
+    /// once we are public.
+    pub(crate) fn maintain(&mut self, swarm: &mut Swarm<Behaviour>) {
+        if !self.enabled {
+            return;
+        }
+        match swarm.behaviour().autonat.nat_status() {
+            NatStatus::Private => {
+                let relays =
+                    self.pick_relays(|peer_id| swarm.is_connected(peer_id), Instant::now());
+                for (relay, addr) in relays {
+                    let circuit_addr = addr.with(Protocol::P2p(relay)).with(Protocol::P2pCircuit);
+                    match swarm.listen_on(circuit_addr.clone()) {
+                        Ok(listener_id) => {
+                            info!("[Relay] - Listening on {circuit_addr}");
+                            self.listeners.insert(listener_id, relay);
+                        }
+                        Err(err) => {
+                            warn!("[Relay] - Failed to listen on {circuit_addr}: {err}");
+                            self.failed.insert(relay, Instant::now());
+                        }
+                    }
+                }
+            }
+            NatStatus::Public(_) => {
+                if !self.listeners.is_empty() {
+                    info!("[Relay] - We are public, the relays are no longer needed");
+                    self.close_listeners(swarm);
+                }
+            }
+            NatStatus::Unknown => {}
+        }
+    }
+
+    /// The relayed listeners we keep open.
+    pub(crate) fn reservation_count(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub(crate) fn close_listeners(&mut self, swarm: &mut Swarm<Behaviour>) {
+        for (listener_id, relay) in self.listeners.drain() {
+            info!("[Relay] - Closing the reservation with {relay}");
+            swarm.remove_listener(listener_id);
+        }
+    }
+
+    /// Picks the relays to fill up the free reservation slots with, preferring the ones we are
+    /// already connected to.
+    fn pick_relays(
+        &mut self,
+        is_connected: impl Fn(&PeerId) -> bool,
+        now: Instant,
+    ) -> Vec<(PeerId, Multiaddr)> {
+        self.failed
+            .retain(|_, failed_at| now.duration_since(*failed_at) < RELAY_RETRY_BACKOFF);
+        let free = RELAY_RESERVATIONS.saturating_sub(self.listeners.len());
+        let mut relays: Vec<_> = self
+            .candidates
+            .iter()
+            .filter(|(peer_id, _)| {
+                !self.failed.contains_key(peer_id)
+                    && self.listeners.values().all(|relay| relay != *peer_id)
+            })
+            .map(|(peer_id, addr)| (*peer_id, addr.clone()))
+            .collect();
+        relays.sort_by_key(|(peer_id, _)| !is_connected(peer_id));
+        relays.truncate(free);
+        relays
+    }
+}
+
+/// Whether the address goes through a relay.
+pub(crate) fn is_relayed(addr: &Multiaddr) -> bool {
+    addr.iter().any(|proto| proto == Protocol::P2pCircuit)
+}
+
+/// Whether peers outside of our own network can dial the address. Loopback, private and link-local
+/// addresses only work for peers next to the relay, so they make bad relayed addresses.
+pub(crate) fn is_global(addr: &Multiaddr) -> bool {
+    if is_relayed(addr) {
+        return false;
+    }
+    match addr.iter().next() {
+        Some(Protocol::Ip4(ip)) => {
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation())
+        }
+        Some(Protocol::Ip6(ip)) => {
+            let first_segment = ip.segments()[0];
+            // NOTE: unique local (fc00::/7) and link-local (fe80::/10) addresses
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || first_segment & 0xfe00 == 0xfc00
+                || first_segment & 0xffc0 == 0xfe80)
+        }
+        Some(Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_)) => {
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity::Keypair;
+    use pretty_assertions::assert_eq;
+
+    fn random_peer_id() -> PeerId {
+        Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    fn addr(port: u16) -> Multiaddr {
+        Multiaddr::empty()
+            .with(Protocol::Ip4([127, 0, 0, 1].into()))
+            .with(Protocol::Tcp(port))
+    }
+
+    #[test]
+    fn test_pick_relays_prefers_connected_peers() {
+        let mut relays = RelayManager::new(true, None);
+        let connected = random_peer_id();
+        for port in 0..4 {
+            relays.add_candidate(random_peer_id(), addr(port));
+        }
+        relays.add_candidate(connected, addr(4));
+        let picked = relays.pick_relays(|peer_id| *peer_id == connected, Instant::now());
+        assert_eq!(picked.len(), RELAY_RESERVATIONS);
+        assert_eq!(picked[0], (connected, addr(4)));
+    }
+
+    #[test]
+    fn test_pick_relays_skips_failed_relays_until_backoff() {
+        let mut relays = RelayManager::new(true, None);
+        let relay = random_peer_id();
+        relays.add_candidate(relay, addr(0));
+        let listener_id = ListenerId::next();
+        relays.listeners.insert(listener_id, relay);
+        assert!(relays.pick_relays(|_| true, Instant::now()).is_empty());
+
+        assert!(relays.on_listener_closed(listener_id));
+        assert!(relays.pick_relays(|_| true, Instant::now()).is_empty());
+        let later = Instant::now() + RELAY_RETRY_BACKOFF;
+        assert_eq!(relays.pick_relays(|_| true, later), vec![(relay, addr(0))]);
+    }
+
+    #[test]
+    fn test_only_global_addresses_are_global() {
+        let global = |addr: &str| is_global(&addr.parse().unwrap());
+        assert!(global("/ip4/8.8.8.8/tcp/1"));
+        assert!(global("/ip6/2001:4860::8888/tcp/1"));
+        assert!(global("/dns4/example.org/tcp/1"));
+        assert!(!global("/ip4/127.0.0.1/tcp/1"));
+        assert!(!global("/ip4/192.168.1.10/tcp/1"));
+        assert!(!global("/ip4/169.254.0.1/tcp/1"));
+        assert!(!global("/ip6/::1/tcp/1"));
+        assert!(!global("/ip6/fd00::1/tcp/1"));
+        assert!(!global("/ip6/fe80::1/tcp/1"));
+        assert!(!global("/memory/1"));
+        let relay = random_peer_id();
+        assert!(!global(&format!(
+            "/ip4/8.8.8.8/tcp/1/p2p/{relay}/p2p-circuit"
+        )));
+    }
+
+    #[test]
+    fn test_unknown_listener_is_ignored() {
+        let mut relays = RelayManager::new(true, None);
+        assert!(!relays.on_listener_closed(ListenerId::next()));
+    }
+}
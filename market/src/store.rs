@@ -0,0 +1,460 @@
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use libp2p::{
+    kad::{
+        store::{self, MemoryStore, RecordStore},
+        ProviderRecord, Record, RecordKey,
+    },
+    Multiaddr, PeerId,
+};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{file_info, keyword, RecordStoreKind};
+
+pub(crate) const STORE_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The record store used by the Kademlia behaviour.
+///
+/// Everything is kept in a [`MemoryStore`]. If the store was created with a path, a snapshot of the
+/// records is written to disk whenever [`MarketStore::flush`] is called after a change. Expired
+/// records are dropped when the snapshot is loaded and are never handed out to other peers.
+///
+/// Keyword records are merged with the record we already have instead of replacing it, see
+/// [`keyword::merge`]. Every other record has to be a file info that hashes to its key, anything
+/// else is not stored.
+pub(crate) struct MarketStore {
+    inner: MemoryStore,
+    local_peer_id: PeerId,
+    // NOTE: MemoryStore can only iterate over the keys we provide ourselves, so we keep track of
+    // every key that has provider records to be able to write them out.
+    provider_keys: HashSet<RecordKey>,
+    path: Option<PathBuf>,
+    dirty: bool,
+}
+
+impl MarketStore {
+    pub(crate) fn new(local_peer_id: PeerId, kind: &RecordStoreKind) -> io::Result<Self> {
+        let mut store = Self {
+            inner: MemoryStore::new(local_peer_id),
+            local_peer_id,
+            provider_keys: HashSet::new(),
+            path: None,
+            dirty: false,
+        };
+        if let RecordStoreKind::Disk(path) = kind {
+            match fs::read(path) {
+                Ok(bytes) => {
+                    let snapshot: StoreSnapshot = serde_json::from_slice(&bytes)
+                        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+                    store.restore(snapshot);
+                }
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            store.path = Some(path.clone());
+        }
+        Ok(store)
+    }
+
+    fn restore(&mut self, snapshot: StoreSnapshot) {
+        let now = SystemTime::now();
+        for record in snapshot.records {
+            if let Some(record) = record.into_record(now) {
+                if let Err(err) = self.put(record) {
+                    error!("[Store] - Failed to restore a record: {err}");
+                }
+            }
+        }
+        for record in snapshot.providers {
+            if let Some(record) = record.into_provider_record(now) {
+                // NOTE: the files we provide ourselves are owned by the local market map, so they
+                // are not brought back from here. Otherwise we would keep republishing files that
+                // we can no longer answer for.
+                if record.provider == self.local_peer_id {
+                    continue;
+                }
+                if let Err(err) = self.add_provider(record) {
+                    error!("[Store] - Failed to restore a provider record: {err}");
+                }
+            }
+        }
+        // NOTE: the snapshot on disk may still contain the expired records
+        self.dirty = true;
+    }
+
+    /// Writes the records to disk if anything changed since the last flush.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let wall_now = SystemTime::now();
+        let records = self
+            .inner
+            .records()
+            .filter(|record| !record.is_expired(now))
+            .map(|record| StoredRecord::new(&record, now, wall_now))
+            .collect();
+        let providers = self
+            .provider_keys
+            .iter()
+            .flat_map(|key| self.providers(key))
+            .map(|record| StoredProviderRecord::new(&record, now, wall_now))
+            .collect();
+        let bytes = serde_json::to_vec(&StoreSnapshot { records, providers })
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        write_atomically(path, &bytes)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl RecordStore for MarketStore {
+    type RecordsIter<'a> = <MemoryStore as RecordStore>::RecordsIter<'a>;
+    type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+    fn get(&self, k: &RecordKey) -> Option<Cow<'_, Record>> {
+        self.inner.get(k)
+    }
+
+    fn put(&mut self, mut r: Record) -> store::Result<()> {
+        if !keyword::is_keyword_key(&r.key) {
+            if let Err(err) = file_info::decode(&r.key, &r.value) {
+                warn!("[Store] - Ignoring an invalid file info record: {err}");
+                return Ok(());
+            }
+        } else {
+            if let Some(existing) = self
+                .inner
+                .get(&r.key)
+                .filter(|existing| !existing.is_expired(Instant::now()))
+            {
+                match keyword::merge(&existing.value, &r.value) {
+                    Ok(value) => r.value = value,
+                    Err(err) => {
+                        warn!("[Store] - Ignoring an invalid keyword record: {err}");
+                        return Ok(());
+                    }
+                }
+                // NOTE: we keep republishing the record if we published it ourselves
+                if existing.publisher == Some(self.local_peer_id) {
+                    r.publisher = existing.publisher;
+                }
+            }
+        }
+        self.inner.put(r)?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn remove(&mut self, k: &RecordKey) {
+        self.inner.remove(k);
+        self.dirty = true;
+    }
+
+    fn records(&self) -> Self::RecordsIter<'_> {
+        self.inner.records()
+    }
+
+    fn add_provider(&mut self, record: ProviderRecord) -> store::Result<()> {
+        let key = record.key.clone();
+        self.inner.add_provider(record)?;
+        self.provider_keys.insert(key);
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn providers(&self, key: &RecordKey) -> Vec<ProviderRecord> {
+        let now = Instant::now();
+        self.inner
+            .providers(key)
+            .into_iter()
+            .filter(|record| !record.is_expired(now))
+            .collect()
+    }
+
+    fn provided(&self) -> Self::ProvidedIter<'_> {
+        self.inner.provided()
+    }
+
+    fn remove_provider(&mut self, k: &RecordKey, p: &PeerId) {
+        self.inner.remove_provider(k, p);
+        if self.inner.providers(k).is_empty() {
+            self.provider_keys.remove(k);
+        }
+        self.dirty = true;
+    }
+}
+
+impl Drop for MarketStore {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            error!("[Store] - Failed to flush the record store: {err}");
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreSnapshot {
+    records: Vec<StoredRecord>,
+    providers: Vec<StoredProviderRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRecord {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    publisher: Option<Vec<u8>>,
+    expires_at_ms: Option<u64>,
+}
+
+impl StoredRecord {
+    fn new(record: &Record, now: Instant, wall_now: SystemTime) -> Self {
+        Self {
+            key: record.key.to_vec(),
+            value: record.value.clone(),
+            publisher: record.publisher.map(|peer_id| peer_id.to_bytes()),
+            expires_at_ms: record
+                .expires
+                .map(|expires| to_unix_ms(expires, now, wall_now)),
+        }
+    }
+
+    fn into_record(self, wall_now: SystemTime) -> Option<Record> {
+        let expires = match self.expires_at_ms {
+            Some(expires_at_ms) => Some(from_unix_ms(expires_at_ms, wall_now)?),
+            None => None,
+        };
+        let publisher = match self.publisher {
+            Some(bytes) => Some(PeerId::from_bytes(&bytes).ok()?),
+            None => None,
+        };
+        Some(Record {
+            key: RecordKey::from(self.key),
+            value: self.value,
+            publisher,
+            expires,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredProviderRecord {
+    key: Vec<u8>,
+    provider: Vec<u8>,
+    addresses: Vec<Multiaddr>,
+    expires_at_ms: Option<u64>,
+}
+
+impl StoredProviderRecord {
+    fn new(record: &ProviderRecord, now: Instant, wall_now: SystemTime) -> Self {
+        Self {
+            key: record.key.to_vec(),
+            provider: record.provider.to_bytes(),
+            addresses: record.addresses.clone(),
+            expires_at_ms: record
+                .expires
+                .map(|expires| to_unix_ms(expires, now, wall_now)),
+        }
+    }
+
+    fn into_provider_record(self, wall_now: SystemTime) -> Option<ProviderRecord> {
+        let expires = match self.expires_at_ms {
+            Some(expires_at_ms) => Some(from_unix_ms(expires_at_ms, wall_now)?),
+            None => None,
+        };
+        Some(ProviderRecord {
+            key: RecordKey::from(self.key),
+            provider: PeerId::from_bytes(&self.provider).ok()?,
+            expires,
+            addresses: self.addresses,
+        })
+    }
+}
+
+/// Writes the file through a temporary file next to it, so a crash mid-write leaves the previous
+/// version in place instead of a corrupted file.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    write_atomically_with(path, bytes, OpenOptions::new())
+}
+
+/// Like [`write_atomically`], but the temporary file is created with `options`, e.g. to restrict
+/// who can read it. The temporary file is synced before it replaces the file.
+pub(crate) fn write_atomically_with(
+    path: &Path,
+    bytes: &[u8],
+    mut options: OpenOptions,
+) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    // NOTE: a leftover of an earlier crash would keep its permissions, so start from scratch
+    match fs::remove_file(&tmp_path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let mut file = options.write(true).create_new(true).open(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Converts the monotonic expiry of a record to a wall clock time that survives a restart.
+fn to_unix_ms(expires: Instant, now: Instant, wall_now: SystemTime) -> u64 {
+    let expires_at = wall_now + expires.saturating_duration_since(now);
+    expires_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Converts a stored wall clock expiry back to an [`Instant`], or `None` if it already expired.
+fn from_unix_ms(expires_at_ms: u64, wall_now: SystemTime) -> Option<Instant> {
+    let expires_at = UNIX_EPOCH + Duration::from_millis(expires_at_ms);
+    let remaining = expires_at.duration_since(wall_now).ok()?;
+    if remaining.is_zero() {
+        None
+    } else {
+        Some(Instant::now() + remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity::Keypair;
+    use pretty_assertions::assert_eq;
+    use proto::market::{FileInfo, FileInfoHash};
+
+    fn store_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("orcanet-store-{name}-{}.json", std::process::id()))
+    }
+
+    fn random_peer_id() -> PeerId {
+        Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    #[test]
+    fn test_provider_records_survive_reload() {
+        let path = store_path("reload");
+        let _ = fs::remove_file(&path);
+        let local_peer_id = random_peer_id();
+        let provider = random_peer_id();
+        let key = RecordKey::new(&"some file");
+        let kind = RecordStoreKind::Disk(path.clone());
+        {
+            let mut store = MarketStore::new(local_peer_id, &kind).unwrap();
+            let mut record = ProviderRecord::new(key.clone(), provider, vec![]);
+            record.expires = Some(Instant::now() + Duration::from_secs(60));
+            store.add_provider(record).unwrap();
+            store.flush().unwrap();
+        }
+        let store = MarketStore::new(local_peer_id, &kind).unwrap();
+        let providers = store.providers(&key);
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].provider, provider);
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_expired_provider_records_are_dropped_on_reload() {
+        let path = store_path("expired");
+        let _ = fs::remove_file(&path);
+        let local_peer_id = random_peer_id();
+        let key = RecordKey::new(&"some file");
+        let kind = RecordStoreKind::Disk(path.clone());
+        {
+            let mut store = MarketStore::new(local_peer_id, &kind).unwrap();
+            let mut record = ProviderRecord::new(key.clone(), random_peer_id(), vec![]);
+            record.expires = Some(Instant::now() + Duration::from_millis(10));
+            store.add_provider(record).unwrap();
+            store.flush().unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        let store = MarketStore::new(local_peer_id, &kind).unwrap();
+        assert!(store.providers(&key).is_empty());
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_local_provider_records_are_not_restored() {
+        let path = store_path("local");
+        let _ = fs::remove_file(&path);
+        let local_peer_id = random_peer_id();
+        let key = RecordKey::new(&"some file");
+        let kind = RecordStoreKind::Disk(path.clone());
+        {
+            let mut store = MarketStore::new(local_peer_id, &kind).unwrap();
+            let record = ProviderRecord::new(key.clone(), local_peer_id, vec![]);
+            store.add_provider(record).unwrap();
+        }
+        let store = MarketStore::new(local_peer_id, &kind).unwrap();
+        assert!(store.providers(&key).is_empty());
+        assert_eq!(store.provided().count(), 0);
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_keyword_records_are_merged() {
+        let local_peer_id = random_peer_id();
+        let mut store = MarketStore::new(local_peer_id, &RecordStoreKind::Memory).unwrap();
+        let key = keyword::record_key("holiday");
+        let a = FileInfoHash::new("a".to_owned());
+        let b = FileInfoHash::new("b".to_owned());
+
+        let mut record = Record::new(key.clone(), keyword::encode(std::slice::from_ref(&a)));
+        record.publisher = Some(local_peer_id);
+        store.put(record).unwrap();
+        let mut record = Record::new(key.clone(), keyword::encode(std::slice::from_ref(&b)));
+        record.publisher = Some(random_peer_id());
+        store.put(record).unwrap();
+
+        let record = store.get(&key).unwrap();
+        assert_eq!(keyword::decode(&record.value).unwrap(), vec![a, b]);
+        assert_eq!(record.publisher, Some(local_peer_id));
+    }
+
+    #[test]
+    fn test_only_valid_file_info_records_are_stored() {
+        let mut store = MarketStore::new(random_peer_id(), &RecordStoreKind::Memory).unwrap();
+        let file_info = FileInfo {
+            file_hash: "foo".to_string(),
+            chunk_hashes: vec!["1".into(), "2".into()],
+            file_size: 8000,
+            file_name: "a_file".to_string(),
+        };
+        let key = file_info::record_key(file_info.get_hash());
+
+        let mut tampered = file_info.clone();
+        tampered.file_name = "another_file".to_string();
+        store
+            .put(Record::new(key.clone(), file_info::encode(&tampered)))
+            .unwrap();
+        assert!(store.get(&key).is_none());
+        store.put(Record::new(key.clone(), vec![1, 2, 3])).unwrap();
+        assert!(store.get(&key).is_none());
+
+        store
+            .put(Record::new(key.clone(), file_info::encode(&file_info)))
+            .unwrap();
+        assert_eq!(
+            store.get(&key).unwrap().value,
+            file_info::encode(&file_info)
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Runs several peers in one process over the memory transport, so that multi-node tests don't
+//! need real sockets or hard-coded ports, and waits for what the peers do in the background.
+
+use std::{
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use thiserror::Error;
+use tokio::time::{sleep, timeout, Instant};
+
+use crate::{
+    bridge::{spawn, BridgeError},
+    BootNodes, Config, ConfigBuilder, KadMode, Peer, SuccessfulResponse,
+};
+
+/// Every network takes its ports from here so that networks of tests running at the same time
+/// never share a memory port.
+static NEXT_MEMORY_PORT: AtomicU64 = AtomicU64::new(1);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How long [`TestNetwork::spawn`] waits for its peers to connect, and a sensible deadline for
+/// [`poll_until`]. Generous, since a loaded CI machine can take a while.
+pub const DEFAULT_DEADLINE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum TestNetworkError {
+    #[error(transparent)]
+    Bridge(#[from] BridgeError),
+    #[error("{peer} didn't see a connection with {other} within {deadline:?}")]
+    NotConnected {
+        peer: PeerId,
+        other: PeerId,
+        deadline: Duration,
+    },
+}
+
+/// A group of peers connected over the memory transport.
+///
+/// The first peer is the boot node of every other peer. Once [`TestNetwork::spawn`] returns, the
+/// boot node is connected to every peer and every peer knows the boot node, so Kademlia queries
+/// reach the whole network.
+#[derive(Debug)]
+pub struct TestNetwork {
+    peers: Vec<Peer>,
+}
+
+impl TestNetwork {
+    /// Spawns `size` peers in Kademlia server mode without the relay client and DCUtR, since the
+    /// memory transport can't be reached from outside the process anyway.
+    pub async fn spawn(size: usize) -> Result<Self, TestNetworkError> {
+        Self::spawn_with(size, |_, builder| builder).await
+    }
+
+    /// Like [`TestNetwork::spawn`], but every peer's config goes through `configure` together
+    /// with the index of the peer first. The transport and the boot nodes are always overwritten.
+    pub async fn spawn_with(
+        size: usize,
+        configure: impl Fn(usize, ConfigBuilder) -> ConfigBuilder,
+    ) -> Result<Self, TestNetworkError> {
+        let first_port = NEXT_MEMORY_PORT.fetch_add(size as u64, Ordering::Relaxed);
+        let mut peers: Vec<Peer> = Vec::with_capacity(size);
+        let mut boot_node_addr: Option<Multiaddr> = None;
+        for (index, port) in (first_port..first_port + size as u64).enumerate() {
+            let mut builder = configure(
+                index,
+                Config::builder()
+                    .set_kad_mode(KadMode::Server)
+                    .set_relay_client(false)
+                    .set_dcutr(false)
+                    .set_coordinator_thread_name(format!("coordinator-{index}")),
+            )
+            .set_memory_transport(port);
+            if let Some(addr) = &boot_node_addr {
+                builder = builder.set_boot_nodes(BootNodes::with_nodes(vec![addr.clone()]));
+            }
+            let peer = spawn(builder.build())?;
+            if boot_node_addr.is_none() {
+                boot_node_addr = Some(
+                    Multiaddr::from(Protocol::Memory(port)).with(Protocol::P2p(*peer.peer_id())),
+                );
+            }
+            peers.push(peer);
+        }
+
+        if let Some((boot_node, rest)) = peers.split_first() {
+            for peer in rest {
+                wait_until_connected(boot_node, peer, DEFAULT_DEADLINE).await?;
+                wait_until_connected(peer, boot_node, DEFAULT_DEADLINE).await?;
+            }
+        }
+        Ok(Self { peers })
+    }
+
+    #[inline(always)]
+    pub fn peers(&self) -> &[Peer] {
+        &self.peers
+    }
+
+    /// # Panics
+    /// If there is no peer at `index`.
+    #[inline(always)]
+    pub fn peer(&self, index: usize) -> &Peer {
+        &self.peers[index]
+    }
+
+    #[inline(always)]
+    pub fn boot_node(&self) -> &Peer {
+        self.peer(0)
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Shuts every peer down, the boot node last.
+    pub async fn shutdown(self) {
+        for peer in self.peers.iter().rev() {
+            let _ = peer.shutdown().await;
+        }
+    }
+}
+
+/// Waits until `peer` sees a connection with `other`, for at most `deadline`.
+// NOTE: the listener can see the connection before the dialer does, so ask both sides
+pub async fn wait_until_connected(
+    peer: &Peer,
+    other: &Peer,
+    deadline: Duration,
+) -> Result<(), TestNetworkError> {
+    let connected = async {
+        while peer.connected_to(*other.peer_id()).await
+            != Ok(SuccessfulResponse::ConnectedTo { connected: true })
+        {
+            sleep(POLL_INTERVAL).await;
+        }
+    };
+    timeout(deadline, connected)
+        .await
+        .map_err(|_| TestNetworkError::NotConnected {
+            peer: *peer.peer_id(),
+            other: *other.peer_id(),
+            deadline,
+        })
+}
+
+/// Calls `poll` until `done` holds for what it returned or `deadline` has passed. The last result
+/// is returned either way, so the caller can assert on it.
+pub async fn poll_until<T, Fut>(
+    deadline: Duration,
+    mut poll: impl FnMut() -> Fut,
+    done: impl Fn(&T) -> bool,
+) -> T
+where
+    Fut: Future<Output = T>,
+{
+    let give_up_at = Instant::now() + deadline;
+    let mut last = poll().await;
+    while !done(&last) && Instant::now() < give_up_at {
+        sleep(POLL_INTERVAL).await;
+        last = poll().await;
+    }
+    last
+}
@@ -0,0 +1,32 @@
+//! The supplier and files the integration tests register.
+#![allow(dead_code)]
+
+use proto::market::{FileInfo, User};
+
+pub fn user() -> User {
+    User {
+        id: "abc".to_string(),
+        name: "helloworld".to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 6666,
+        price: 32,
+    }
+}
+
+pub fn file_info() -> FileInfo {
+    FileInfo {
+        file_hash: "123abc".to_string(),
+        chunk_hashes: vec!["hi".to_string()],
+        file_size: 3212321,
+        file_name: "fooobar.mp4".to_owned(),
+    }
+}
+
+/// Like [`file_info`], but with another name and a hash of its own.
+pub fn file_info_named(file_name: &str) -> FileInfo {
+    FileInfo {
+        file_hash: format!("{file_name}-hash"),
+        file_name: file_name.to_owned(),
+        ..file_info()
+    }
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::time::Duration;
+
+use orcanet_market::{
+    testing::TestNetwork, Announcement, FailureResponse, KadFailureResponse, LmmFailureResponse,
+    SuccessfulResponse,
+};
+use proto::market::FileInfo;
+use tokio::{sync::broadcast, time::timeout};
+
+use common::{file_info, user};
+
+async fn next_announcement(
+    announcements: &mut broadcast::Receiver<Announcement>,
+) -> Option<Announcement> {
+    timeout(Duration::from_millis(200), announcements.recv())
+        .await
+        .ok()
+        .and_then(Result::ok)
+}
+
+#[tokio::test]
+async fn test_register_file_and_price_change_are_announced() {
+    let network = TestNetwork::spawn(3).await.unwrap();
+    let supplier = network.peer(1);
+    let mut announcements = network.peer(2).subscribe_announcements();
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+
+    // NOTE: the gossipsub mesh is only formed on the next heartbeat, so the first announcements
+    // might not reach anybody
+    let mut announcement = None;
+    for _ in 0..50 {
+        let _ = supplier
+            .register_file(user(), file_info_hash.clone(), file_info.clone())
+            .await;
+        announcement = next_announcement(&mut announcements).await;
+        if announcement.is_some() {
+            break;
+        }
+    }
+    assert_eq!(
+        announcement,
+        Some(Announcement {
+            peer_id: *supplier.peer_id(),
+            file_info_hash: file_info_hash.clone(),
+            file_info: file_info.clone(),
+            price: 32,
+        })
+    );
+
+    assert_eq!(
+        supplier.set_price(file_info_hash.clone(), 64).await,
+        Ok(SuccessfulResponse::SetPrice)
+    );
+    let mut announcement = next_announcement(&mut announcements).await;
+    // NOTE: skip the repeated registrations that were still on their way
+    while announcement.as_ref().is_some_and(|a| a.price == 32) {
+        announcement = next_announcement(&mut announcements).await;
+    }
+    assert_eq!(
+        announcement,
+        Some(Announcement {
+            peer_id: *supplier.peer_id(),
+            file_info_hash,
+            file_info,
+            price: 64,
+        })
+    );
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_set_price_of_unregistered_file() {
+    let network = TestNetwork::spawn(1).await.unwrap();
+    let file_info_hash = file_info().get_hash();
+    assert_eq!(
+        network
+            .boot_node()
+            .set_price(file_info_hash.clone(), 64)
+            .await,
+        Err(FailureResponse::LmmError(
+            LmmFailureResponse::NotRegistered { file_info_hash }
+        ))
+    );
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_file_info_too_large_to_announce_is_refused() {
+    let network = TestNetwork::spawn(1).await.unwrap();
+    let file_info = FileInfo {
+        chunk_hashes: vec!["a".repeat(64); 1024],
+        ..file_info()
+    };
+    let file_info_hash = file_info.get_hash();
+    assert!(matches!(
+        network
+            .boot_node()
+            .register_file(user(), file_info_hash.clone(), file_info)
+            .await,
+        Err(FailureResponse::KadError(
+            KadFailureResponse::RegisterFile { .. }
+        ))
+    ));
+    assert_eq!(
+        network
+            .boot_node()
+            .set_price(file_info_hash.clone(), 64)
+            .await,
+        Err(FailureResponse::LmmError(
+            LmmFailureResponse::NotRegistered { file_info_hash }
+        ))
+    );
+    network.shutdown().await;
+}
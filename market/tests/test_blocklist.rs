@@ -0,0 +1,118 @@
+use libp2p::PeerId;
+use orcanet_market::{
+    bridge::spawn_in_current_runtime,
+    testing::{poll_until, TestNetwork, DEFAULT_DEADLINE},
+    Config, ConnectionLimits, FailureResponse, Multiaddr, Peer, SuccessfulResponse,
+};
+
+async fn listener(peer: &Peer) -> Multiaddr {
+    match peer.listeners().await {
+        Ok(SuccessfulResponse::Listeners { listeners }) => listeners[0].clone(),
+        res => panic!("unexpected response {res:?}"),
+    }
+}
+
+async fn in_routing_table(peer: &Peer, peer_id: PeerId) -> bool {
+    match peer.routing_table().await {
+        Ok(SuccessfulResponse::RoutingTable { buckets }) => buckets
+            .into_iter()
+            .flat_map(|bucket| bucket.entries)
+            .any(|entry| entry.peer_id == peer_id),
+        res => panic!("unexpected response {res:?}"),
+    }
+}
+
+async fn wait_until_disconnected(peer: &Peer, other: PeerId) {
+    let connected = poll_until(
+        DEFAULT_DEADLINE,
+        || peer.connected_to(other),
+        |res| *res == Ok(SuccessfulResponse::ConnectedTo { connected: false }),
+    )
+    .await;
+    assert_eq!(
+        connected,
+        Ok(SuccessfulResponse::ConnectedTo { connected: false }),
+        "{other} is still connected"
+    );
+}
+
+#[tokio::test]
+async fn test_block_and_unblock_peer() {
+    let network = TestNetwork::spawn(2).await.unwrap();
+    let boot_node = network.boot_node();
+    let peer = network.peer(1);
+    let peer_id = *peer.peer_id();
+
+    assert_eq!(
+        boot_node.block_peer(peer_id).await,
+        Ok(SuccessfulResponse::BlockPeer)
+    );
+    wait_until_disconnected(boot_node, peer_id).await;
+    assert!(!in_routing_table(boot_node, peer_id).await);
+    // NOTE: a blocked peer can finish the handshake with us, but we close the connection right
+    // away, so only our own dials fail
+    assert!(matches!(
+        boot_node.dial(listener(peer).await).await,
+        Err(FailureResponse::DialError(_))
+    ));
+    assert!(matches!(
+        boot_node
+            .add_peer_address(peer_id, listener(peer).await)
+            .await,
+        Err(FailureResponse::AddPeerAddressError(_))
+    ));
+
+    assert_eq!(
+        boot_node.unblock_peer(peer_id).await,
+        Ok(SuccessfulResponse::UnblockPeer)
+    );
+    assert_eq!(
+        boot_node.dial(listener(peer).await).await,
+        Ok(SuccessfulResponse::Dial { peer_id })
+    );
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_connections_per_peer_are_limited() {
+    let network = TestNetwork::spawn_with(2, |index, builder| {
+        if index == 0 {
+            builder.set_connection_limits(ConnectionLimits {
+                max_established_per_peer: Some(1),
+                ..Default::default()
+            })
+        } else {
+            builder
+        }
+    })
+    .await
+    .unwrap();
+    let boot_node = network.boot_node();
+    let peer = network.peer(1);
+
+    // NOTE: the boot node is already connected to the peer
+    assert!(matches!(
+        boot_node.dial(listener(peer).await).await,
+        Err(FailureResponse::DialError(_))
+    ));
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_only_allowed_peers_can_be_dialed() {
+    let network = TestNetwork::spawn(1).await.unwrap();
+    let boot_node = network.boot_node();
+    // NOTE: the networks of this test binary take their memory ports from 1 upwards
+    let config = Config::builder()
+        .set_memory_transport(1 << 40)
+        .set_allowed_peers([])
+        .build();
+    let peer = spawn_in_current_runtime(config).await.unwrap();
+
+    assert!(matches!(
+        peer.dial(listener(boot_node).await).await,
+        Err(FailureResponse::DialError(_))
+    ));
+    let _ = peer.shutdown().await;
+    network.shutdown().await;
+}
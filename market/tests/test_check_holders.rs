@@ -1,15 +1,19 @@
-use std::net::Ipv4Addr;
+use std::{net::Ipv4Addr, time::Duration};
 
+use futures::StreamExt;
 use libp2p::Multiaddr;
 use orcanet_market::{
-    bridge::spawn, BootNodes, Config, FileResponse, Protocol, ReqResSuccessfulResponse,
-    SuccessfulResponse, SupplierInfo,
+    bridge::spawn, BootNodes, Config, FileResponse, HolderFailure, HolderFailureReason, KadMode,
+    Protocol, ReqResSuccessfulResponse, SuccessfulResponse, SupplierInfo,
 };
 use proto::market::{FileInfo, FileInfoHash, HoldersResponse, User};
 
 #[tokio::test]
 async fn test_register_file_and_get_self_holder() {
-    let config = Config::builder().set_peer_tcp_port(3390).build();
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(3390)
+        .build();
     let peer = spawn(config).unwrap();
     let peer_id = *peer.peer_id();
     let user = User {
@@ -46,7 +50,10 @@ async fn test_register_file_and_get_self_holder() {
 
 #[tokio::test]
 async fn test_register_file_and_check_holders_basic() {
-    let config = Config::builder().set_peer_tcp_port(3391).build();
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(3391)
+        .build();
     let peer = spawn(config).unwrap();
     let user = User {
         id: "abc".to_string(),
@@ -70,12 +77,21 @@ async fn test_register_file_and_check_holders_basic() {
         .register_file(user, file_info_hash.clone(), file_info)
         .await;
     let res = peer.check_holders(file_info_hash).await;
-    assert_eq!(res, Ok(SuccessfulResponse::CheckHolders(expected_holders)))
+    assert_eq!(
+        res,
+        Ok(SuccessfulResponse::CheckHolders {
+            response: expected_holders,
+            failures: vec![]
+        })
+    )
 }
 
 #[tokio::test]
 async fn test_check_holders_from_other_peer() {
-    let config = Config::builder().set_peer_tcp_port(3392).build();
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(3392)
+        .build();
     let peer1 = spawn(config).unwrap();
     let mut addr = Multiaddr::empty();
     addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
@@ -84,6 +100,7 @@ async fn test_check_holders_from_other_peer() {
 
     let boot_nodes = BootNodes::with_nodes(vec![addr]);
     let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
         .set_peer_tcp_port(3393)
         .set_boot_nodes(boot_nodes)
         .build();
@@ -112,5 +129,128 @@ async fn test_check_holders_from_other_peer() {
         .register_file(user, file_info_hash.clone(), file_info)
         .await;
     let res = peer2.check_holders(file_info_hash).await;
-    assert_eq!(res, Ok(SuccessfulResponse::CheckHolders(expected_holders)))
+    assert_eq!(
+        res,
+        Ok(SuccessfulResponse::CheckHolders {
+            response: expected_holders,
+            failures: vec![]
+        })
+    )
+}
+
+#[tokio::test]
+async fn test_check_holders_reports_stale_provider() {
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(3394)
+        .build();
+    let peer1 = spawn(config).unwrap();
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(3394));
+    addr.push(Protocol::P2p(*peer1.peer_id()));
+
+    let boot_nodes = BootNodes::with_nodes(vec![addr]);
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(3395)
+        .set_boot_nodes(boot_nodes)
+        .build();
+    let peer2 = spawn(config).unwrap();
+    // NOTE: peer1 has to know about peer2 so the provider record ends up on peer2
+    while peer1.connected_to(*peer2.peer_id()).await
+        != Ok(SuccessfulResponse::ConnectedTo { connected: true })
+    {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let user = User {
+        id: "abc".to_string(),
+        name: "helloworld".to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 6666,
+        price: 32,
+    };
+    let file_info = FileInfo {
+        file_hash: "123abc".to_string(),
+        chunk_hashes: vec!["hi".to_string()],
+        file_size: 3212321,
+        file_name: "fooobar.mp4".to_owned(),
+    };
+    let file_info_hash = file_info.get_hash();
+    let _ = peer1
+        .register_file(user, file_info_hash.clone(), file_info.clone())
+        .await;
+    // NOTE: AddProvider isn't acknowledged, so give the record some time to reach peer2
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    // NOTE: peer2 still has the provider record, but peer1 doesn't hold the file anymore
+    let _ = peer1.unregister_file(file_info_hash.clone()).await;
+
+    // NOTE: the file info still comes from its DHT record
+    let res = peer2.check_holders(file_info_hash).await;
+    assert_eq!(
+        res,
+        Ok(SuccessfulResponse::CheckHolders {
+            response: HoldersResponse {
+                file_info: Some(file_info),
+                holders: vec![]
+            },
+            failures: vec![HolderFailure {
+                peer_id: *peer1.peer_id(),
+                reason: HolderFailureReason::NoFile
+            }]
+        })
+    )
+}
+
+#[tokio::test]
+async fn test_check_holders_stream_from_other_peer() {
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(3396)
+        .build();
+    let peer1 = spawn(config).unwrap();
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(3396));
+    addr.push(Protocol::P2p(*peer1.peer_id()));
+
+    let boot_nodes = BootNodes::with_nodes(vec![addr]);
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(3397)
+        .set_boot_nodes(boot_nodes)
+        .build();
+    let peer2 = spawn(config).unwrap();
+
+    let user = User {
+        id: "abc".to_string(),
+        name: "helloworld".to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 6666,
+        price: 32,
+    };
+    let file_info = FileInfo {
+        file_hash: "123abc".to_string(),
+        chunk_hashes: vec!["hi".to_string()],
+        file_size: 3212321,
+        file_name: "fooobar.mp4".to_owned(),
+    };
+    let expected_holder = SupplierInfo {
+        file_info: file_info.clone(),
+        user: user.clone(),
+    };
+
+    let file_info_hash = file_info.get_hash();
+    let _ = peer1
+        .register_file(user, file_info_hash.clone(), file_info)
+        .await;
+    let holders: Vec<SupplierInfo> = peer2
+        .check_holders_stream(file_info_hash)
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    assert_eq!(holders, vec![expected_holder]);
 }
@@ -0,0 +1,98 @@
+mod common;
+
+use libp2p::Multiaddr;
+use orcanet_market::{
+    bridge::{spawn, spawn_in_current_runtime, BridgeError},
+    testing::{wait_until_connected, DEFAULT_DEADLINE},
+    BootNodes, Config, KadMode, Protocol, SuccessfulResponse,
+};
+use proto::market::HoldersResponse;
+
+use common::{file_info, user};
+
+const BOOT_NODE_PORT: u64 = 1;
+const PEER_PORT: u64 = 2;
+const STANDALONE_PORT: u64 = 3;
+const CORRUPT_KEYPAIR_PORT: u64 = 4;
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_check_holders_with_coordinators_on_the_current_runtime() {
+    let boot_node = spawn_in_current_runtime(
+        Config::builder()
+            .set_kad_mode(KadMode::Server)
+            .set_memory_transport(BOOT_NODE_PORT)
+            .build(),
+    )
+    .await
+    .unwrap();
+    let boot_node_addr =
+        Multiaddr::from(Protocol::Memory(BOOT_NODE_PORT)).with(Protocol::P2p(*boot_node.peer_id()));
+    let peer = spawn_in_current_runtime(
+        Config::builder()
+            .set_kad_mode(KadMode::Server)
+            .set_memory_transport(PEER_PORT)
+            .set_boot_nodes(BootNodes::with_nodes(vec![boot_node_addr]))
+            .build(),
+    )
+    .await
+    .unwrap();
+    wait_until_connected(&peer, &boot_node, DEFAULT_DEADLINE)
+        .await
+        .unwrap();
+
+    let user = user();
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    let _ = boot_node
+        .register_file(user.clone(), file_info_hash.clone(), file_info.clone())
+        .await;
+    assert_eq!(
+        peer.check_holders(file_info_hash).await,
+        Ok(SuccessfulResponse::CheckHolders {
+            response: HoldersResponse {
+                file_info: Some(file_info),
+                holders: vec![user],
+            },
+            failures: vec![]
+        })
+    );
+
+    assert_eq!(peer.shutdown().await, Ok(SuccessfulResponse::Shutdown));
+    assert_eq!(boot_node.shutdown().await, Ok(SuccessfulResponse::Shutdown));
+}
+
+#[test]
+fn test_spawn_with_one_worker_thread() {
+    let peer = spawn(
+        Config::builder()
+            .set_memory_transport(STANDALONE_PORT)
+            .set_worker_threads(1)
+            .build(),
+    )
+    .unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    assert_eq!(
+        runtime.block_on(peer.shutdown()),
+        Ok(SuccessfulResponse::Shutdown)
+    );
+}
+
+#[tokio::test]
+async fn test_spawn_failure_within_a_runtime_is_returned() {
+    let path = std::env::temp_dir().join(format!(
+        "orcanet-corrupt-keypair-{}.key",
+        std::process::id()
+    ));
+    std::fs::write(&path, b"not a keypair").unwrap();
+    let res = spawn(
+        Config::builder()
+            .set_memory_transport(CORRUPT_KEYPAIR_PORT)
+            .set_keypair_path(&path)
+            .build(),
+    );
+    let _ = std::fs::remove_file(&path);
+    assert!(matches!(res, Err(BridgeError::Keypair(_))));
+}
@@ -0,0 +1,106 @@
+use libp2p::PeerId;
+use orcanet_market::{
+    testing::{poll_until, TestNetwork, DEFAULT_DEADLINE},
+    FailureResponse, Multiaddr, Protocol, SuccessfulResponse,
+};
+
+#[tokio::test]
+async fn test_disconnect_and_dial_again() {
+    let network = TestNetwork::spawn(2).await.unwrap();
+    let boot_node = network.boot_node();
+    let peer = network.peer(1);
+
+    assert_eq!(
+        peer.disconnect(*boot_node.peer_id()).await,
+        Ok(SuccessfulResponse::Disconnect)
+    );
+    // NOTE: the connections are closed in the background
+    let connected = poll_until(
+        DEFAULT_DEADLINE,
+        || peer.connected_to(*boot_node.peer_id()),
+        |res| *res != Ok(SuccessfulResponse::ConnectedTo { connected: true }),
+    )
+    .await;
+    assert_eq!(
+        connected,
+        Ok(SuccessfulResponse::ConnectedTo { connected: false })
+    );
+
+    let address = match boot_node.listeners().await {
+        Ok(SuccessfulResponse::Listeners { listeners }) => listeners[0].clone(),
+        res => panic!("unexpected response {res:?}"),
+    };
+    assert_eq!(
+        peer.dial(address).await,
+        Ok(SuccessfulResponse::Dial {
+            peer_id: *boot_node.peer_id()
+        })
+    );
+    assert_eq!(
+        peer.connected_to(*boot_node.peer_id()).await,
+        Ok(SuccessfulResponse::ConnectedTo { connected: true })
+    );
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_dial_and_disconnect_failures() {
+    let network = TestNetwork::spawn(2).await.unwrap();
+    let boot_node = network.boot_node();
+    let peer = network.peer(1);
+
+    // NOTE: nobody listens on this port
+    let unreachable = Multiaddr::from(Protocol::Memory(1 << 40));
+    assert!(matches!(
+        peer.dial(unreachable).await,
+        Err(FailureResponse::DialError(_))
+    ));
+
+    let address = match boot_node.listeners().await {
+        Ok(SuccessfulResponse::Listeners { listeners }) => listeners[0].clone(),
+        res => panic!("unexpected response {res:?}"),
+    };
+    let wrong_peer_id = address.with(Protocol::P2p(PeerId::random()));
+    assert!(matches!(
+        peer.dial(wrong_peer_id).await,
+        Err(FailureResponse::DialError(_))
+    ));
+
+    assert!(matches!(
+        peer.disconnect(PeerId::random()).await,
+        Err(FailureResponse::DisconnectError(_))
+    ));
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_add_peer_address() {
+    let network = TestNetwork::spawn(1).await.unwrap();
+    let peer = network.boot_node();
+    let peer_id = PeerId::random();
+    let address = Multiaddr::from(Protocol::Memory(1 << 40));
+
+    assert_eq!(
+        peer.add_peer_address(peer_id, address.clone()).await,
+        Ok(SuccessfulResponse::AddPeerAddress)
+    );
+    let buckets = match peer.routing_table().await {
+        Ok(SuccessfulResponse::RoutingTable { buckets }) => buckets,
+        res => panic!("unexpected response {res:?}"),
+    };
+    let entry = buckets
+        .into_iter()
+        .flat_map(|bucket| bucket.entries)
+        .find(|entry| entry.peer_id == peer_id)
+        .expect("the peer to be in the routing table");
+    assert_eq!(entry.addresses, vec![address.with(Protocol::P2p(peer_id))]);
+
+    // NOTE: the address is of another peer
+    let other_peer_id = PeerId::random();
+    let address = Multiaddr::from(Protocol::Memory(1 << 40)).with(Protocol::P2p(other_peer_id));
+    assert!(matches!(
+        peer.add_peer_address(peer_id, address).await,
+        Err(FailureResponse::AddPeerAddressError(_))
+    ));
+    network.shutdown().await;
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::{net::Ipv4Addr, time::Duration};
+
+use libp2p::Multiaddr;
+use orcanet_market::{bridge::spawn, BootNodes, Config, MarketEvent, Protocol};
+use tokio::sync::broadcast::Receiver;
+
+use common::{file_info, user};
+
+/// Waits for the first event that matches `predicate`.
+async fn wait_for(
+    receiver: &mut Receiver<MarketEvent>,
+    predicate: impl Fn(&MarketEvent) -> bool,
+) -> MarketEvent {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = receiver.recv().await.unwrap();
+            if predicate(&event) {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("the event to arrive in time")
+}
+
+#[tokio::test]
+async fn test_subscribe_to_connections_and_holder_requests() {
+    let config = Config::builder().set_peer_tcp_port(3414).build();
+    let peer1 = spawn(config).unwrap();
+    let mut events1 = peer1.subscribe();
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(3414));
+    addr.push(Protocol::P2p(*peer1.peer_id()));
+    let config = Config::builder()
+        .set_peer_tcp_port(3415)
+        .set_boot_nodes(BootNodes::with_nodes(vec![addr]))
+        .build();
+    let peer2 = spawn(config).unwrap();
+    let mut events2 = peer2.subscribe();
+    let peer1_id = *peer1.peer_id();
+    let peer2_id = *peer2.peer_id();
+
+    wait_for(&mut events1, |event| {
+        matches!(event, MarketEvent::ConnectionEstablished { peer_id, .. } if *peer_id == peer2_id)
+    })
+    .await;
+
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    let _ = peer1
+        .register_file(user(), file_info_hash.clone(), file_info)
+        .await;
+    let _ = peer2
+        .get_holder_by_peer_id(peer1_id, file_info_hash.clone())
+        .await;
+
+    let event = wait_for(&mut events1, |event| {
+        matches!(event, MarketEvent::HolderRequested { .. })
+    })
+    .await;
+    assert_eq!(
+        event,
+        MarketEvent::HolderRequested {
+            peer_id: peer2_id,
+            file_info_hash,
+            has_file: true
+        }
+    );
+    let event = wait_for(&mut events2, |event| {
+        matches!(event, MarketEvent::HolderAnswered { .. })
+    })
+    .await;
+    assert_eq!(
+        event,
+        MarketEvent::HolderAnswered {
+            peer_id: peer1_id,
+            has_file: true
+        }
+    );
+}
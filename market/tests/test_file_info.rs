@@ -0,0 +1,54 @@
+mod common;
+
+use orcanet_market::{
+    testing::{poll_until, TestNetwork, DEFAULT_DEADLINE},
+    KadSuccessfulResponse, Peer, SuccessfulResponse,
+};
+use proto::market::{FileInfo, FileInfoHash};
+
+use common::{file_info, user};
+
+async fn get_file_info(peer: &Peer, file_info_hash: FileInfoHash) -> Option<FileInfo> {
+    match peer.get_file_info(file_info_hash).await {
+        Ok(SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetFileInfo { file_info })) => {
+            file_info
+        }
+        res => panic!("unexpected response {res:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_check_holders_returns_file_info_without_holders() {
+    let network = TestNetwork::spawn(3).await.unwrap();
+    let supplier = network.peer(1);
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    assert_eq!(
+        get_file_info(network.boot_node(), file_info_hash.clone()).await,
+        None
+    );
+
+    supplier
+        .register_file(user(), file_info_hash.clone(), file_info.clone())
+        .await
+        .unwrap();
+    // NOTE: the file info record is stored in the background
+    let found = poll_until(
+        DEFAULT_DEADLINE,
+        || get_file_info(network.boot_node(), file_info_hash.clone()),
+        Option::is_some,
+    )
+    .await;
+    assert_eq!(found, Some(file_info.clone()));
+
+    // NOTE: the only holder is gone, but the record is still on the other peers
+    let _ = supplier.shutdown().await;
+    match network.boot_node().check_holders(file_info_hash).await {
+        Ok(SuccessfulResponse::CheckHolders { response, .. }) => {
+            assert_eq!(response.file_info, Some(file_info));
+            assert!(response.holders.is_empty());
+        }
+        res => panic!("unexpected response {res:?}"),
+    }
+    network.shutdown().await;
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::{net::Ipv4Addr, num::NonZeroUsize, time::Duration};
+
+use libp2p::{Multiaddr, PeerId};
+use orcanet_market::{
+    bridge::spawn, BootNodes, Config, KadMode, KadSuccessfulResponse, Peer, Protocol,
+    SuccessfulResponse,
+};
+
+use common::{file_info, user};
+
+/// Spawns a boot node on `boot_port` and one peer for every port in `ports` that boots from it.
+async fn spawn_network(boot_port: u16, ports: &[u16]) -> (Peer, Vec<Peer>) {
+    let boot_node = spawn(
+        Config::builder()
+            .set_kad_mode(KadMode::Server)
+            .set_peer_tcp_port(boot_port)
+            .build(),
+    )
+    .unwrap();
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(boot_port));
+    addr.push(Protocol::P2p(*boot_node.peer_id()));
+    let peers: Vec<Peer> = ports
+        .iter()
+        .map(|port| {
+            let config = Config::builder()
+                .set_kad_mode(KadMode::Server)
+                .set_peer_tcp_port(*port)
+                .set_boot_nodes(BootNodes::with_nodes(vec![addr.clone()]))
+                .build();
+            spawn(config).unwrap()
+        })
+        .collect();
+    for peer in &peers {
+        while boot_node.connected_to(*peer.peer_id()).await
+            != Ok(SuccessfulResponse::ConnectedTo { connected: true })
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+    // NOTE: give identify some time to fill the routing tables
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    (boot_node, peers)
+}
+
+fn providers(res: orcanet_market::Response) -> Vec<PeerId> {
+    match res {
+        Ok(SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetProviders {
+            mut providers,
+        })) => {
+            providers.sort();
+            providers
+        }
+        res => panic!("unexpected response {res:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_get_providers_from_several_nodes() {
+    let (_boot_node, peers) = spawn_network(3400, &[3401, 3402, 3403, 3404]).await;
+    let (consumer, suppliers) = peers.split_last().unwrap();
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    for supplier in suppliers {
+        let _ = supplier
+            .register_file(user(), file_info_hash.clone(), file_info.clone())
+            .await;
+    }
+    // NOTE: AddProvider isn't acknowledged, so give the records some time to arrive
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let res = consumer.get_providers(file_info_hash).await;
+    let mut expected: Vec<PeerId> = suppliers.iter().map(|peer| *peer.peer_id()).collect();
+    expected.sort();
+    assert_eq!(providers(res), expected);
+}
+
+#[tokio::test]
+async fn test_get_providers_until_quorum() {
+    let (_boot_node, peers) = spawn_network(3405, &[3406, 3407, 3408]).await;
+    let (consumer, suppliers) = peers.split_last().unwrap();
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    for supplier in suppliers {
+        let _ = supplier
+            .register_file(user(), file_info_hash.clone(), file_info.clone())
+            .await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let res = consumer
+        .get_providers_until(file_info_hash, NonZeroUsize::new(1), None)
+        .await;
+    let providers = providers(res);
+    assert!(!providers.is_empty());
+    assert!(providers
+        .iter()
+        .all(|provider| suppliers.iter().any(|peer| peer.peer_id() == provider)));
+}
+
+#[tokio::test]
+async fn test_get_providers_until_deadline() {
+    let (_boot_node, peers) = spawn_network(3409, &[3410]).await;
+    let file_info_hash = file_info().get_hash();
+    let res = peers[0]
+        .get_providers_until(file_info_hash, None, Some(Duration::from_millis(1)))
+        .await;
+    assert_eq!(providers(res), vec![]);
+}
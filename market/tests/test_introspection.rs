@@ -0,0 +1,65 @@
+use orcanet_market::{
+    testing::{poll_until, TestNetwork, DEFAULT_DEADLINE},
+    ConnectionRole, ExternalAddress, Multiaddr, Protocol, SuccessfulResponse,
+};
+
+#[tokio::test]
+async fn test_routing_table_and_connections() {
+    let network = TestNetwork::spawn(3).await.unwrap();
+    let boot_node = network.boot_node();
+    let peer = network.peer(1);
+
+    // NOTE: the boot node only adds the peer once identify told it the peer's listen addresses
+    let entry = poll_until(
+        DEFAULT_DEADLINE,
+        || async {
+            let buckets = match boot_node.routing_table().await {
+                Ok(SuccessfulResponse::RoutingTable { buckets }) => buckets,
+                res => panic!("unexpected response {res:?}"),
+            };
+            buckets
+                .into_iter()
+                .flat_map(|bucket| bucket.entries)
+                .find(|entry| entry.peer_id == *peer.peer_id())
+        },
+        Option::is_some,
+    )
+    .await;
+    let entry = entry.expect("the peer to be in the routing table of the boot node");
+    assert!(entry
+        .addresses
+        .iter()
+        .all(|address| matches!(address.iter().next(), Some(Protocol::Memory(_)))));
+
+    let connections = match peer.connections().await {
+        Ok(SuccessfulResponse::Connections { connections }) => connections,
+        res => panic!("unexpected response {res:?}"),
+    };
+    let connection = connections
+        .iter()
+        .find(|connection| connection.peer_id == *boot_node.peer_id())
+        .expect("the peer to be connected to the boot node");
+    assert_eq!(connection.role, ConnectionRole::Dialer);
+    assert!(!connection.relayed);
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_public_address_is_a_confirmed_external_address() {
+    let public_address: Multiaddr = "/ip4/1.2.3.4/tcp/4321".parse().unwrap();
+    let network = TestNetwork::spawn_with(1, |_, builder| {
+        builder.set_public_address(public_address.clone())
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        network.boot_node().external_addresses().await,
+        Ok(SuccessfulResponse::ExternalAddresses {
+            addresses: vec![ExternalAddress {
+                address: public_address.clone(),
+                confirmed: true,
+            }]
+        })
+    );
+    network.shutdown().await;
+}
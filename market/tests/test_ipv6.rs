@@ -0,0 +1,47 @@
+use std::{net::Ipv6Addr, time::Duration};
+
+use libp2p::Multiaddr;
+use orcanet_market::{bridge::spawn, BootNodes, Config, Protocol, SuccessfulResponse};
+
+#[tokio::test]
+async fn test_boot_from_ipv6_node() {
+    let config = Config::builder().set_peer_tcp_port(3428).build();
+    let peer1 = spawn(config).unwrap();
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip6(Ipv6Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(3428));
+    addr.push(Protocol::P2p(*peer1.peer_id()));
+    let config = Config::builder()
+        .set_peer_tcp_port(3429)
+        .set_boot_nodes(BootNodes::with_nodes(vec![addr]))
+        .build();
+    let peer2 = spawn(config).unwrap();
+    let connected = tokio::time::timeout(Duration::from_secs(2), async {
+        while peer2.connected_to(*peer1.peer_id()).await
+            != Ok(SuccessfulResponse::ConnectedTo { connected: true })
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(connected.is_ok());
+}
+
+#[tokio::test]
+async fn test_listens_on_ipv4_and_ipv6() {
+    let config = Config::builder().set_peer_tcp_port(3430).build();
+    let peer = spawn(config).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    match peer.listeners().await {
+        Ok(SuccessfulResponse::Listeners { listeners }) => {
+            let has = |is_host: fn(&Protocol) -> bool| {
+                listeners
+                    .iter()
+                    .any(|addr| addr.iter().any(|proto| is_host(&proto)))
+            };
+            assert!(has(|proto| matches!(proto, Protocol::Ip4(_))));
+            assert!(has(|proto| matches!(proto, Protocol::Ip6(_))));
+        }
+        res => panic!("unexpected response {res:?}"),
+    }
+}
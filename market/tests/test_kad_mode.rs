@@ -0,0 +1,97 @@
+mod common;
+
+use std::{net::Ipv4Addr, time::Duration};
+
+use libp2p::{Multiaddr, PeerId};
+use orcanet_market::{
+    bridge::spawn,
+    testing::{wait_until_connected, DEFAULT_DEADLINE},
+    BootNodes, Config, KadMode, KadSuccessfulResponse, Peer, Protocol, SuccessfulResponse,
+};
+
+use common::{file_info, user};
+
+fn addr(port: u16, peer_id: PeerId) -> Multiaddr {
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(port));
+    addr.push(Protocol::P2p(peer_id));
+    addr
+}
+
+/// Spawns a supplier and a consumer in server mode that both only know about `boot_node`, and
+/// returns the providers the consumer finds for the file the supplier registered.
+async fn providers_through(
+    boot_node: &Peer,
+    boot_port: u16,
+    ports: [u16; 2],
+) -> (PeerId, Vec<PeerId>) {
+    let boot_addr = addr(boot_port, *boot_node.peer_id());
+    let [supplier, consumer] = ports.map(|port| {
+        let config = Config::builder()
+            .set_kad_mode(KadMode::Server)
+            .set_peer_tcp_port(port)
+            .set_boot_nodes(BootNodes::with_nodes(vec![boot_addr.clone()]))
+            .build();
+        spawn(config).unwrap()
+    });
+    for peer in [&supplier, &consumer] {
+        wait_until_connected(boot_node, peer, DEFAULT_DEADLINE)
+            .await
+            .unwrap();
+    }
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    let _ = supplier
+        .register_file(user(), file_info_hash.clone(), file_info)
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let providers = match consumer.get_providers(file_info_hash).await {
+        Ok(SuccessfulResponse::KadResponse(KadSuccessfulResponse::GetProviders { providers })) => {
+            providers
+        }
+        _ => vec![],
+    };
+    (*supplier.peer_id(), providers)
+}
+
+#[tokio::test]
+async fn test_public_address_makes_the_boot_node_a_server() {
+    let keypair = libp2p::identity::Keypair::generate_ed25519();
+    let public_address = addr(3419, keypair.public().to_peer_id());
+    let config = Config::builder()
+        .set_peer_tcp_port(3419)
+        .set_keypair(keypair)
+        .set_public_address(public_address)
+        .build();
+    let boot_node = spawn(config).unwrap();
+    let (supplier, providers) = providers_through(&boot_node, 3419, [3420, 3421]).await;
+    assert_eq!(providers, vec![supplier]);
+}
+
+#[tokio::test]
+async fn test_unreachable_boot_node_stays_a_client() {
+    let config = Config::builder().set_peer_tcp_port(3422).build();
+    let boot_node = spawn(config).unwrap();
+    let (_, providers) = providers_through(&boot_node, 3422, [3423, 3424]).await;
+    assert_eq!(providers, vec![]);
+}
+
+#[tokio::test]
+async fn test_relayed_address_keeps_the_boot_node_a_client() {
+    let keypair = libp2p::identity::Keypair::generate_ed25519();
+    let relay = libp2p::identity::Keypair::generate_ed25519()
+        .public()
+        .to_peer_id();
+    let relayed_address = addr(3444, relay)
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(keypair.public().to_peer_id()));
+    let config = Config::builder()
+        .set_peer_tcp_port(3441)
+        .set_keypair(keypair)
+        .set_public_address(relayed_address)
+        .build();
+    let boot_node = spawn(config).unwrap();
+    let (_, providers) = providers_through(&boot_node, 3441, [3442, 3443]).await;
+    assert_eq!(providers, vec![]);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use orcanet_market::{
+    testing::{poll_until, TestNetwork, DEFAULT_DEADLINE},
+    SuccessfulResponse,
+};
+use proto::market::User;
+
+use common::{file_info, user};
+
+#[tokio::test]
+async fn test_memory_network_is_connected_to_the_boot_node() {
+    let network = TestNetwork::spawn(4).await.unwrap();
+    assert_eq!(network.len(), 4);
+    for peer in &network.peers()[1..] {
+        assert_eq!(
+            peer.connected_to(*network.boot_node().peer_id()).await,
+            Ok(SuccessfulResponse::ConnectedTo { connected: true })
+        );
+    }
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_check_holders_across_memory_network() {
+    let network = TestNetwork::spawn(8).await.unwrap();
+    let (consumer, suppliers) = network.peers()[1..].split_last().unwrap();
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    let mut expected_holders = Vec::new();
+    for (index, supplier) in suppliers.iter().enumerate() {
+        let name = format!("supplier{index}");
+        let user = User {
+            id: name.clone(),
+            name,
+            ..user()
+        };
+        expected_holders.push(user.clone());
+        let _ = supplier
+            .register_file(user, file_info_hash.clone(), file_info.clone())
+            .await;
+    }
+    // NOTE: AddProvider isn't acknowledged, so ask until the records have spread
+    let res = poll_until(
+        DEFAULT_DEADLINE,
+        || consumer.check_holders(file_info_hash.clone()),
+        |res| {
+            matches!(res, Ok(SuccessfulResponse::CheckHolders { response, .. })
+                if response.holders.len() == expected_holders.len())
+        },
+    )
+    .await;
+    match res {
+        Ok(SuccessfulResponse::CheckHolders {
+            mut response,
+            failures,
+        }) => {
+            response.holders.sort_by(|a, b| a.id.cmp(&b.id));
+            assert_eq!(response.file_info, Some(file_info));
+            assert_eq!(response.holders, expected_holders);
+            assert!(failures.is_empty());
+        }
+        res => panic!("unexpected response {res:?}"),
+    }
+    network.shutdown().await;
+}
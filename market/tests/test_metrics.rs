@@ -0,0 +1,72 @@
+mod common;
+
+use orcanet_market::{
+    testing::{poll_until, TestNetwork, DEFAULT_DEADLINE},
+    KadQueryKind, MetricsSnapshot, SuccessfulResponse,
+};
+
+use common::{file_info, user};
+
+fn metrics(res: orcanet_market::Response) -> MetricsSnapshot {
+    match res {
+        Ok(SuccessfulResponse::Metrics { metrics }) => metrics,
+        res => panic!("unexpected response {res:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_metrics_after_check_holders() {
+    let network = TestNetwork::spawn(3).await.unwrap();
+    let supplier = network.peer(1);
+    let consumer = network.peer(2);
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    let _ = supplier
+        .register_file(user(), file_info_hash.clone(), file_info)
+        .await;
+    // NOTE: AddProvider isn't acknowledged, so ask until the record has spread
+    let _ = poll_until(
+        DEFAULT_DEADLINE,
+        || consumer.check_holders(file_info_hash.clone()),
+        |res| {
+            matches!(res, Ok(SuccessfulResponse::CheckHolders { response, .. })
+                if !response.holders.is_empty())
+        },
+    )
+    .await;
+
+    // NOTE: the supplier only counts the answer once it was sent, which can be after the consumer
+    // got it
+    let supplier_metrics = poll_until(
+        DEFAULT_DEADLINE,
+        || async { metrics(supplier.metrics().await) },
+        |metrics| metrics.req_res_successes > 0,
+    )
+    .await;
+    assert_eq!(supplier_metrics.local_market_map_size, 1);
+    assert!(supplier_metrics.connected_peers >= 1);
+    assert!(supplier_metrics.routing_table_size >= 1);
+    assert_eq!(
+        supplier_metrics.kad_queries[&KadQueryKind::StartProviding].succeeded,
+        1
+    );
+    assert_eq!(supplier_metrics.req_res_successes, 1);
+
+    let consumer_metrics = metrics(consumer.metrics().await);
+    assert_eq!(consumer_metrics.local_market_map_size, 0);
+    assert_eq!(consumer_metrics.req_res_successes, 1);
+    assert_eq!(consumer_metrics.req_res_failures, 0);
+    assert!(consumer_metrics.kad_queries[&KadQueryKind::GetProviders].succeeded >= 1);
+
+    match consumer.prometheus_metrics().await {
+        Ok(SuccessfulResponse::PrometheusMetrics { text }) => {
+            assert!(text.contains("orcanet_market_routing_table_size"));
+            assert!(text.contains("orcanet_market_kad_query_duration_seconds_bucket"));
+            assert!(text.contains(
+                "orcanet_market_req_res_total{direction=\"Outbound\",outcome=\"Success\"} 1"
+            ));
+        }
+        res => panic!("unexpected response {res:?}"),
+    }
+    network.shutdown().await;
+}
@@ -0,0 +1,65 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use libp2p::Multiaddr;
+use orcanet_market::{
+    bridge::spawn, BootNodes, Config, MarketEvent, NatStatus, Protocol, SuccessfulResponse,
+};
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn test_nat_status_starts_unknown() {
+    let config = Config::builder().set_peer_tcp_port(3416).build();
+    let peer = spawn(config).unwrap();
+    assert_eq!(
+        peer.nat_status().await,
+        Ok(SuccessfulResponse::NatStatus {
+            status: NatStatus::Unknown,
+            confidence: 0
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_nat_status_after_probing_boot_node() {
+    let config = Config::builder().set_peer_tcp_port(3417).build();
+    let peer1 = spawn(config).unwrap();
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(3417));
+    addr.push(Protocol::P2p(*peer1.peer_id()));
+    let config = Config::builder()
+        .set_peer_tcp_port(3418)
+        .set_boot_nodes(BootNodes::with_nodes(vec![addr]))
+        .build();
+    let peer2 = spawn(config).unwrap();
+    // NOTE: AutoNat only starts probing after its boot delay, so nothing is missed here
+    let mut events = peer2.subscribe();
+
+    // NOTE: the boot node won't dial back a loopback address
+    let probe = timeout(Duration::from_secs(10), async {
+        loop {
+            if let event @ (MarketEvent::NatProbeSucceeded { .. }
+            | MarketEvent::NatProbeFailed { .. }) = events.recv().await.unwrap()
+            {
+                break event;
+            }
+        }
+    })
+    .await
+    .expect("peer2 to probe the boot node");
+    assert_eq!(
+        probe,
+        MarketEvent::NatProbeFailed {
+            server: Some(*peer1.peer_id()),
+            error: "Response(DialRefused)".to_owned(),
+        }
+    );
+    // NOTE: a refused dial back says nothing about our reachability
+    assert_eq!(
+        peer2.nat_status().await,
+        Ok(SuccessfulResponse::NatStatus {
+            status: NatStatus::Unknown,
+            confidence: 0
+        })
+    );
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use libp2p::Multiaddr;
+use orcanet_market::{bridge::spawn, Config, Protocol, SuccessfulResponse};
+
+fn is_quic(addr: &Multiaddr, port: u16) -> bool {
+    let protocols: Vec<_> = addr.iter().collect();
+    protocols.contains(&Protocol::Udp(port)) && protocols.contains(&Protocol::QuicV1)
+}
+
+#[tokio::test]
+async fn test_listens_on_quic_port() {
+    let config = Config::builder()
+        .set_peer_tcp_port(3425)
+        .set_peer_quic_port(3426)
+        .build();
+    let peer = spawn(config).unwrap();
+    let listeners = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            if let Ok(SuccessfulResponse::Listeners { listeners }) = peer.listeners().await {
+                if listeners.iter().any(|addr| is_quic(addr, 3426)) {
+                    return listeners;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("to listen on the QUIC port");
+    assert!(listeners
+        .iter()
+        .any(|addr| addr.iter().any(|proto| proto == Protocol::Tcp(3425))));
+}
+
+#[tokio::test]
+async fn test_quic_is_off_by_default() {
+    let config = Config::builder().set_peer_tcp_port(3427).build();
+    let peer = spawn(config).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    match peer.listeners().await {
+        Ok(SuccessfulResponse::Listeners { listeners }) => {
+            assert!(!listeners.is_empty());
+            assert!(listeners
+                .iter()
+                .all(|addr| addr.iter().all(|proto| proto != Protocol::QuicV1)));
+        }
+        res => panic!("unexpected response {res:?}"),
+    }
+}
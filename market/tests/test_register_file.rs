@@ -1,4 +1,4 @@
-use orcanet_market::{bridge::spawn, Config, KadSuccessfulResponse, SuccessfulResponse};
+use orcanet_market::{bridge::spawn, Config, KadMode, KadSuccessfulResponse, SuccessfulResponse};
 use proto::market::{FileInfo, FileInfoHash, User};
 
 #[tokio::test]
@@ -29,7 +29,10 @@ async fn test_register_file() {
 
 #[tokio::test]
 async fn test_register_and_get_providers_for_one() {
-    let config = Config::builder().set_peer_tcp_port(14000).build();
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(14000)
+        .build();
     let peer = spawn(config).unwrap();
     let peer_id = peer.peer_id();
     let user = User {
@@ -60,3 +63,56 @@ async fn test_register_and_get_providers_for_one() {
         ))
     );
 }
+
+#[tokio::test]
+async fn test_registered_files_are_provided_after_restart() {
+    let path =
+        std::env::temp_dir().join(format!("orcanet-lmm-restart-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(14001)
+        .set_local_market_map_path(&path)
+        .build();
+    let peer = spawn(config).unwrap();
+    let user = User {
+        id: "abc".to_string(),
+        name: "helloworld".to_string(),
+        ip: "127.0.0.1".to_string(),
+        port: 6666,
+        price: 32,
+    };
+    let file_info = FileInfo {
+        file_hash: "123abc".to_string(),
+        chunk_hashes: vec!["hi".to_string()],
+        file_size: 3212321,
+        file_name: "fooobar.mp4".to_owned(),
+    };
+    let file_info_hash = file_info.get_hash();
+    let _ = peer
+        .register_file(user, file_info_hash.clone(), file_info)
+        .await;
+
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(14002)
+        .set_local_market_map_path(&path)
+        .build();
+    let restarted_peer = spawn(config).unwrap();
+    assert_eq!(
+        restarted_peer
+            .is_local_file_owner(file_info_hash.clone())
+            .await,
+        Ok(true)
+    );
+    let res = restarted_peer.get_providers(file_info_hash).await;
+    assert_eq!(
+        res,
+        Ok(SuccessfulResponse::KadResponse(
+            KadSuccessfulResponse::GetProviders {
+                providers: vec![*restarted_peer.peer_id()]
+            }
+        ))
+    );
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use orcanet_market::{
+    bridge::spawn,
+    testing::{poll_until, TestNetwork, DEFAULT_DEADLINE},
+    BootNodes, Config, KadMode, Peer, Protocol, SearchResult, SuccessfulResponse,
+};
+
+use common::{file_info_named, user};
+
+async fn search(peer: &Peer, query: &str) -> Vec<SearchResult> {
+    match peer.search(query).await {
+        Ok(SuccessfulResponse::Search { results }) => results,
+        res => panic!("unexpected response {res:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_search_ranks_by_matching_keywords() {
+    let network = TestNetwork::spawn(3).await.unwrap();
+    let video = file_info_named("My Holiday_Video.mp4");
+    let photos = file_info_named("holiday-photos.zip");
+    network
+        .peer(1)
+        .register_file(user(), video.get_hash(), video.clone())
+        .await
+        .unwrap();
+    network
+        .peer(2)
+        .register_file(user(), photos.get_hash(), photos.clone())
+        .await
+        .unwrap();
+
+    // NOTE: the keyword records are stored in the background
+    let results = poll_until(
+        DEFAULT_DEADLINE,
+        || search(network.boot_node(), "holiday VIDEO"),
+        |results| results.len() == 2,
+    )
+    .await;
+    assert_eq!(
+        results,
+        vec![
+            SearchResult {
+                file_info_hash: video.get_hash(),
+                file_info: video,
+                matching_keywords: 2,
+            },
+            SearchResult {
+                file_info_hash: photos.get_hash(),
+                file_info: photos,
+                matching_keywords: 1,
+            },
+        ]
+    );
+
+    assert!(search(network.boot_node(), "nothing like it")
+        .await
+        .is_empty());
+    assert!(search(network.boot_node(), "").await.is_empty());
+    network.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_full_keyword_record_reaches_a_new_peer() {
+    // NOTE: as many files as a keyword record holds
+    const FULL_RECORD: usize = 128;
+    let network = TestNetwork::spawn(2).await.unwrap();
+    for i in 0..FULL_RECORD {
+        let file_info = file_info_named(&format!("shared {i}"));
+        network
+            .peer(1)
+            .register_file(user(), file_info.get_hash(), file_info)
+            .await
+            .unwrap();
+    }
+
+    // NOTE: the new peer has no copy of the record, so it has to come over the wire
+    let boot_node = network.boot_node();
+    let boot_addr = match boot_node.listeners().await {
+        Ok(SuccessfulResponse::Listeners { listeners }) => listeners[0]
+            .clone()
+            .with(Protocol::P2p(*boot_node.peer_id())),
+        res => panic!("unexpected response {res:?}"),
+    };
+    // NOTE: the networks of this test binary take their memory ports from 1 upwards
+    let config = Config::builder()
+        .set_memory_transport(1 << 40)
+        .set_kad_mode(KadMode::Server)
+        .set_boot_nodes(BootNodes::with_nodes(vec![boot_addr]))
+        .build();
+    let peer = spawn(config).unwrap();
+    let results = poll_until(
+        DEFAULT_DEADLINE,
+        || search(&peer, "shared"),
+        |results| results.len() == FULL_RECORD,
+    )
+    .await;
+    assert_eq!(results.len(), FULL_RECORD);
+    let _ = peer.shutdown().await;
+    network.shutdown().await;
+}
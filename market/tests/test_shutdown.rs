@@ -0,0 +1,77 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use libp2p::Multiaddr;
+use orcanet_market::{
+    bridge::spawn, BootNodes, Config, FailureResponse, Protocol, SuccessfulResponse,
+};
+
+#[tokio::test]
+async fn test_shutdown_disconnects_peers() {
+    let config = Config::builder().set_peer_tcp_port(3411).build();
+    let peer1 = spawn(config).unwrap();
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(3411));
+    addr.push(Protocol::P2p(*peer1.peer_id()));
+    let config = Config::builder()
+        .set_peer_tcp_port(3412)
+        .set_boot_nodes(BootNodes::with_nodes(vec![addr]))
+        .build();
+    let peer2 = spawn(config).unwrap();
+    let peer2_id = *peer2.peer_id();
+    while peer1.connected_to(peer2_id).await
+        != Ok(SuccessfulResponse::ConnectedTo { connected: true })
+    {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(peer2.shutdown().await, Ok(SuccessfulResponse::Shutdown));
+    // NOTE: the other side notices the connection closing a little later
+    let disconnected = tokio::time::timeout(Duration::from_secs(2), async {
+        while peer1.connected_to(peer2_id).await
+            != Ok(SuccessfulResponse::ConnectedTo { connected: false })
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(disconnected.is_ok());
+    assert!(matches!(
+        peer2.listeners().await,
+        Err(FailureResponse::SendError(_))
+    ));
+    let file_info_hash = "123abc".to_string();
+    assert!(matches!(
+        peer2.check_holders(file_info_hash.clone()).await,
+        Err(FailureResponse::SendError(_))
+    ));
+    assert!(matches!(
+        peer2.check_holders_stream(file_info_hash.clone()).await,
+        Err(FailureResponse::SendError(_))
+    ));
+    assert!(matches!(
+        peer2.is_local_file_owner(file_info_hash).await,
+        Err(FailureResponse::SendError(_))
+    ));
+    assert!(matches!(
+        peer2.search("holiday").await,
+        Err(FailureResponse::SendError(_))
+    ));
+    // NOTE: shutting down twice is fine
+    assert_eq!(peer2.shutdown().await, Ok(SuccessfulResponse::Shutdown));
+}
+
+#[tokio::test]
+async fn test_shutdown_frees_the_port() {
+    let config = Config::builder().set_peer_tcp_port(3413).build();
+    let peer = spawn(config).unwrap();
+    assert_eq!(peer.shutdown().await, Ok(SuccessfulResponse::Shutdown));
+
+    let config = Config::builder().set_peer_tcp_port(3413).build();
+    let peer = spawn(config).unwrap();
+    assert!(matches!(
+        peer.listeners().await,
+        Ok(SuccessfulResponse::Listeners { .. })
+    ));
+    assert_eq!(peer.shutdown().await, Ok(SuccessfulResponse::Shutdown));
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::net::Ipv4Addr;
+
+use libp2p::Multiaddr;
+use orcanet_market::{
+    bridge::spawn, BootNodes, Config, FileResponse, KadMode, KadSuccessfulResponse, Protocol,
+    ReqResSuccessfulResponse, SuccessfulResponse,
+};
+use proto::market::HoldersResponse;
+
+use common::{file_info, user};
+
+#[tokio::test]
+async fn test_unregister_file_from_self() {
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(14010)
+        .build();
+    let peer = spawn(config).unwrap();
+    let peer_id = *peer.peer_id();
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    let _ = peer
+        .register_file(user(), file_info_hash.clone(), file_info)
+        .await;
+    let res = peer.unregister_file(file_info_hash.clone()).await;
+    assert_eq!(
+        res,
+        Ok(SuccessfulResponse::KadResponse(
+            KadSuccessfulResponse::UnregisterFile
+        ))
+    );
+    assert_eq!(
+        peer.is_local_file_owner(file_info_hash.clone()).await,
+        Ok(false)
+    );
+    let res = peer.get_holder_by_peer_id(peer_id, file_info_hash).await;
+    assert_eq!(
+        res,
+        Ok(SuccessfulResponse::ReqResResponse(
+            ReqResSuccessfulResponse::GetHolderByPeerId {
+                holder: FileResponse::NoFile
+            }
+        ))
+    );
+}
+
+#[tokio::test]
+async fn test_unregister_file_from_other_peer() {
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(14011)
+        .build();
+    let peer1 = spawn(config).unwrap();
+    let mut addr = Multiaddr::empty();
+    addr.push(Protocol::Ip4(Ipv4Addr::LOCALHOST));
+    addr.push(Protocol::Tcp(14011));
+    addr.push(Protocol::P2p(*peer1.peer_id()));
+    let config = Config::builder()
+        .set_kad_mode(KadMode::Server)
+        .set_peer_tcp_port(14012)
+        .set_boot_nodes(BootNodes::with_nodes(vec![addr]))
+        .build();
+    let peer2 = spawn(config).unwrap();
+
+    let file_info = file_info();
+    let file_info_hash = file_info.get_hash();
+    let _ = peer1
+        .register_file(user(), file_info_hash.clone(), file_info.clone())
+        .await;
+    let res = peer2.check_holders(file_info_hash.clone()).await;
+    assert_eq!(
+        res,
+        Ok(SuccessfulResponse::CheckHolders {
+            response: HoldersResponse {
+                file_info: Some(file_info),
+                holders: vec![user()],
+            },
+            failures: vec![]
+        })
+    );
+
+    let _ = peer1.unregister_file(file_info_hash.clone()).await;
+    let res = peer2
+        .get_holder_by_peer_id(*peer1.peer_id(), file_info_hash)
+        .await;
+    assert_eq!(
+        res,
+        Ok(SuccessfulResponse::ReqResResponse(
+            ReqResSuccessfulResponse::GetHolderByPeerId {
+                holder: FileResponse::NoFile
+            }
+        ))
+    );
+}
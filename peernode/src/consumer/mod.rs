@@ -4,6 +4,7 @@ pub mod http;
 use std::fmt::Write;
 
 use anyhow::{anyhow, Result};
+use orcanet_market::SearchResult;
 use proto::market::{FileInfoHash, User};
 
 use self::http::GetFileResponse;
@@ -37,6 +38,24 @@ pub async fn list_producers(
     Ok(producer_list)
 }
 
+// find the files whose names match the query
+pub async fn search_files(query: &str, client: &mut MarketClient) -> Result<Vec<SearchResult>> {
+    let results = client.search(query).await?;
+    if results.is_empty() {
+        println!("No files found!");
+    }
+    for result in &results {
+        println!(
+            "File: {}, Size: {}, Matches: {}, Hash: {}",
+            result.file_info.file_name,
+            result.file_info.file_size,
+            result.matching_keywords,
+            result.file_info_hash
+        );
+    }
+    Ok(results)
+}
+
 // get file I want by hash from producer
 pub async fn get_file(
     user: User,
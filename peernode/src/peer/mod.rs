@@ -1,9 +1,13 @@
-use orcanet_market::{bridge::spawn, Config, Peer, SuccessfulResponse};
+use orcanet_market::{
+    bridge::spawn_in_current_runtime, Config, ConnectionInfo, MarketEvent, Peer, SearchResult,
+    SuccessfulResponse,
+};
 
 use proto::market::{FileInfo, FileInfoHash, HoldersResponse, User};
 
 use anyhow::{anyhow, Result};
 use std::collections::HashMap;
+use tokio::sync::broadcast;
 
 // test market
 #[cfg(feature = "test_local_market")]
@@ -48,6 +52,21 @@ impl MarketClient {
             }
         }
     }
+    pub async fn unregister_file(&mut self, file_info_hash: FileInfoHash) -> Result<()> {
+        self.local.remove(&file_info_hash);
+        Ok(())
+    }
+    // there is no network here, so the receiver is closed right away
+    pub fn subscribe(&self) -> broadcast::Receiver<MarketEvent> {
+        broadcast::channel(1).1
+    }
+    pub async fn connections(&self) -> Result<Vec<ConnectionInfo>> {
+        Ok(vec![])
+    }
+    // there is no keyword index here, so nothing is ever found
+    pub async fn search(&self, _query: &str) -> Result<Vec<SearchResult>> {
+        Ok(vec![])
+    }
 }
 
 #[cfg(not(feature = "test_local_market"))]
@@ -62,7 +81,7 @@ impl MarketClient {
     //
     // Initialize a new MarketClient, connecting to the given market service address
     pub async fn new(config: Config) -> Result<Self> {
-        let peer = spawn(config)?;
+        let peer = spawn_in_current_runtime(config).await?;
 
         Ok(MarketClient { inner: peer })
     }
@@ -70,7 +89,15 @@ impl MarketClient {
     // Get a list of producers for a given file hash
     pub async fn check_holders(&self, file_info_hash: FileInfoHash) -> Result<HoldersResponse> {
         match self.inner.check_holders(file_info_hash).await {
-            Ok(SuccessfulResponse::CheckHolders(res)) => Ok(res),
+            Ok(SuccessfulResponse::CheckHolders { response, failures }) => {
+                for failure in failures {
+                    eprintln!(
+                        "Could not get holder info from {}: {}",
+                        failure.peer_id, failure.reason
+                    );
+                }
+                Ok(response)
+            }
             Ok(_) => unreachable!(),
             Err(e) => Err(anyhow!("{e}")),
         }
@@ -92,4 +119,35 @@ impl MarketClient {
             Err(e) => Err(anyhow!("{e}")),
         }
     }
+
+    // Stop providing a file
+    pub async fn unregister_file(&mut self, file_info_hash: FileInfoHash) -> Result<()> {
+        match self.inner.unregister_file(file_info_hash).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("{e}")),
+        }
+    }
+
+    // Subscribe to the events of the market peer
+    pub fn subscribe(&self) -> broadcast::Receiver<MarketEvent> {
+        self.inner.subscribe()
+    }
+
+    // Get the open connections of the market peer
+    pub async fn connections(&self) -> Result<Vec<ConnectionInfo>> {
+        match self.inner.connections().await {
+            Ok(SuccessfulResponse::Connections { connections }) => Ok(connections),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(anyhow!("{e}")),
+        }
+    }
+
+    // Find files by the keywords in their names, best matches first
+    pub async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        match self.inner.search(query).await {
+            Ok(SuccessfulResponse::Search { results }) => Ok(results),
+            Ok(_) => unreachable!(),
+            Err(e) => Err(anyhow!("{e}")),
+        }
+    }
 }
@@ -239,6 +239,7 @@ impl Configurations {
         }
         self.props.files.remove(&hash);
         self.write();
+        self.unregister_file(hash).await;
         Ok(())
     }
 
@@ -248,9 +249,19 @@ impl Configurations {
         }
         self.props.files.remove(&hash);
         self.write();
+        self.unregister_file(hash).await;
         Ok(())
     }
 
+    // stop advertising a removed file in the market, if we are connected to it
+    async fn unregister_file(&mut self, hash: FileInfoHash) {
+        if let Some(market_client) = self.market_client.as_mut() {
+            if let Err(e) = market_client.unregister_file(hash.clone()).await {
+                eprintln!("Failed to unregister file {hash} from the market: {e}");
+            }
+        }
+    }
+
     pub fn set_http_client(&mut self, http_client: tokio::task::JoinHandle<()>) {
         self.http_client = Some(http_client);
     }
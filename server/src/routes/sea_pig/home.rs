@@ -3,7 +3,7 @@ use std::path::PathBuf;
 
 use axum::{
     body::Body,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Response},
     routing::{delete, get, post},
@@ -65,6 +65,62 @@ async fn get_file_info(
         .unwrap()
 }
 
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    query: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct SearchHit {
+    hash: String,
+    name: String,
+    size: i64,
+    matchingKeywords: usize,
+}
+
+async fn search_files(
+    State(state): State<ServerState>,
+    Query(SearchParams { query }): Query<SearchParams>,
+) -> impl IntoResponse {
+    let mut config = state.config.lock().await;
+    let results = match config.get_market_client().await {
+        Ok(market) => match market.search(&query).await {
+            Ok(results) => results,
+            Err(e) => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("Could not search files: {e}"),
+                )
+                    .into_response()
+            }
+        },
+        Err(_) => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Could not connect to market",
+            )
+                .into_response()
+        }
+    };
+    let hits: Vec<_> = results
+        .into_iter()
+        .map(|result| SearchHit {
+            hash: result.file_info_hash.to_string(),
+            name: result.file_info.file_name,
+            size: result.file_info.file_size,
+            matchingKeywords: result.matching_keywords,
+        })
+        .collect();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(
+            serde_json::to_string(&hits).expect("to serialize"),
+        ))
+        .unwrap()
+}
+
 #[derive(Deserialize, Serialize)]
 #[allow(non_snake_case)]
 struct UploadParams {
@@ -123,6 +179,7 @@ async fn delete_file(
 pub fn routes() -> Router<ServerState> {
     Router::new()
         .route("/file/:hash/info", get(get_file_info))
+        .route("/search", get(search_files))
         .route("/upload", post(upload_file))
         .route("/file/:hash", delete(delete_file))
 }
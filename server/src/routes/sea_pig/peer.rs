@@ -8,9 +8,10 @@ use axum::{
     routing::{delete, get, post},
     Json, Router,
 };
-use orcanet_market::Peer;
+use orcanet_market::ConnectionInfo;
 use proto::market::User;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 use crate::ServerState;
 
@@ -52,17 +53,51 @@ async fn get_peer(
 }
 
 async fn get_peers(State(state): State<ServerState>) -> impl IntoResponse {
-    let config = state.config.lock().await;
+    let mut config = state.config.lock().await;
+    let connections = match config.get_market_client().await {
+        Ok(market_client) => market_client.connections().await,
+        Err(e) => Err(e),
+    };
+    let connections = match connections {
+        Ok(connections) => connections,
+        Err(e) => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("Failed to get the connections of the market: {e}"),
+            )
+                .into_response()
+        }
+    };
 
-    let peers: Vec<_> = config
-        .get_peers()
+    // a peer can be connected more than once, e.g. over TCP and through a relay
+    let mut connections_by_peer: BTreeMap<String, Vec<ConnectionInfo>> = BTreeMap::new();
+    for connection in connections {
+        connections_by_peer
+            .entry(connection.peer_id.to_string())
+            .or_default()
+            .push(connection);
+    }
+    let peers: Vec<_> = connections_by_peer
         .into_iter()
-        .map(|peer| PeerInfo {
-            Location: todo!(),
-            Latency: todo!(),
-            PeerID: peer.id,
-            Connection: todo!(),
-            OpenStreams: todo!(),
+        .map(|(peer_id, connections)| {
+            let latency = connections
+                .iter()
+                .filter_map(|connection| connection.rtt)
+                .min()
+                .map(|rtt| format!("{}ms", rtt.as_millis()))
+                .unwrap_or_else(|| "unknown".into());
+            let open_streams: Vec<_> = connections
+                .iter()
+                .map(|connection| connection.remote_address.to_string())
+                .collect();
+            PeerInfo {
+                // there is no geolocation for peers yet
+                Location: "unknown".into(),
+                Latency: latency,
+                PeerID: peer_id,
+                Connection: open_streams[0].clone(),
+                OpenStreams: open_streams.join(","),
+            }
         })
         .collect();
 